@@ -0,0 +1,77 @@
+//! The build backend meson generates files for.
+
+use std::{fmt, str::FromStr};
+
+use crate::Error;
+
+/// The backend meson generates build files for: see `--backend` in the Meson documentation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum Backend {
+    Ninja,
+    /// The Visual Studio version is autodetected by meson.
+    Vs,
+    Vs2017,
+    Vs2019,
+    Vs2022,
+    Xcode,
+    /// Only supported for projects without build rules.
+    None,
+}
+
+impl Backend {
+    /// The value passed into `--backend`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Backend::Ninja => "ninja",
+            Backend::Vs => "vs",
+            Backend::Vs2017 => "vs2017",
+            Backend::Vs2019 => "vs2019",
+            Backend::Vs2022 => "vs2022",
+            Backend::Xcode => "xcode",
+            Backend::None => "none",
+        }
+    }
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Backend {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ninja" => Ok(Backend::Ninja),
+            "vs" => Ok(Backend::Vs),
+            "vs2017" => Ok(Backend::Vs2017),
+            "vs2019" => Ok(Backend::Vs2019),
+            "vs2022" => Ok(Backend::Vs2022),
+            "xcode" => Ok(Backend::Xcode),
+            "none" => Ok(Backend::None),
+            _ => Err(Error::UnknownBackend(s.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backend_from_str() {
+        assert_eq!("vs2022".parse::<Backend>().unwrap(), Backend::Vs2022);
+        assert_eq!(Backend::Xcode.to_string(), "xcode");
+        assert!(matches!(
+            "make".parse::<Backend>(),
+            Err(Error::UnknownBackend(_))
+        ));
+    }
+}
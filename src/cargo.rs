@@ -0,0 +1,307 @@
+//! Helpers producing the `cargo:` directives printed by build scripts.
+
+use std::{
+    collections::BTreeMap,
+    env,
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
+
+use crate::Result;
+
+/// The way a library found in the install tree should be linked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum LinkKind {
+    Static,
+    Dylib,
+}
+
+impl LinkKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            LinkKind::Static => "static",
+            LinkKind::Dylib => "dylib",
+        }
+    }
+}
+
+/// Derives the link name and kind from a library file name.
+///
+/// Returns `None` for files that are not libraries, like `.pc` files or
+/// versioned shared objects like `libfoo.so.1`.
+fn parse_library(file_name: &str) -> Option<(String, LinkKind)> {
+    let (stem, extension) = file_name.rsplit_once('.')?;
+    let kind = match extension {
+        "a" | "lib" => LinkKind::Static,
+        "so" | "dylib" | "dll" => LinkKind::Dylib,
+        _ => return None,
+    };
+
+    // MinGW import libraries: `libfoo.dll.a`
+    if stem.ends_with(".dll") {
+        return None;
+    }
+
+    let name = match extension {
+        "lib" | "dll" => stem,
+        _ => stem.strip_prefix("lib").unwrap_or(stem),
+    };
+
+    if name.is_empty() {
+        return None;
+    }
+
+    Some((name.to_string(), kind))
+}
+
+/// Builds `cargo:rustc-link-search` and `cargo:rustc-link-lib` lines for libraries in `lib_dirs`.
+///
+/// Directories that do not exist are skipped. If a library exists in both static and shared
+/// forms, the shared one is preferred unless `static_only` is set.
+pub(crate) fn link_directives(lib_dirs: &[PathBuf], static_only: bool) -> Result<Vec<String>> {
+    let mut lines = Vec::new();
+    let mut libraries: BTreeMap<String, LinkKind> = BTreeMap::new();
+
+    for lib_dir in lib_dirs.iter().filter(|dir| dir.is_dir()) {
+        let mut found = false;
+        for (name, kind) in read_libraries(lib_dir)? {
+            if static_only && kind != LinkKind::Static {
+                continue;
+            }
+
+            found = true;
+            let entry = libraries.entry(name).or_insert(kind);
+            *entry = (*entry).max(kind);
+        }
+
+        if found {
+            lines.push(format!(
+                "cargo:rustc-link-search=native={}",
+                lib_dir.display()
+            ));
+        }
+    }
+
+    lines.extend(
+        libraries
+            .into_iter()
+            .map(|(name, kind)| format!("cargo:rustc-link-lib={}={}", kind.as_str(), name)),
+    );
+
+    Ok(lines)
+}
+
+fn read_libraries(dir: &Path) -> Result<Vec<(String, LinkKind)>> {
+    let mut libraries = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            continue;
+        }
+
+        if let Some(library) = entry.file_name().to_str().and_then(parse_library) {
+            libraries.push(library);
+        }
+    }
+
+    Ok(libraries)
+}
+
+/// The variable the dynamic linker of `target_os` searches for shared libraries.
+pub(crate) fn runtime_library_path_var(target_os: &str) -> &'static str {
+    match target_os {
+        "windows" => "PATH",
+        "macos" | "ios" => "DYLD_LIBRARY_PATH",
+        _ => "LD_LIBRARY_PATH",
+    }
+}
+
+/// Builds a `cargo:rustc-env` line prepending the existing `lib_dirs` to the `var` search path.
+pub(crate) fn runtime_library_path(
+    var: &str,
+    lib_dirs: &[PathBuf],
+    current: Option<OsString>,
+) -> Result<Vec<String>> {
+    let mut paths: Vec<PathBuf> = lib_dirs
+        .iter()
+        .filter(|dir| dir.is_dir())
+        .cloned()
+        .collect();
+    if paths.is_empty() {
+        return Ok(Vec::new());
+    }
+    if let Some(ref current) = current {
+        paths.extend(env::split_paths(current));
+    }
+
+    let joined = env::join_paths(paths)
+        .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidInput, err))?;
+    Ok(vec![format!(
+        "cargo:rustc-env={var}={}",
+        joined.to_string_lossy()
+    )])
+}
+
+/// Files defining the meson build.
+const MESON_BUILD_FILES: &[&str] = &["meson.build", "meson_options.txt", "meson.options"];
+
+/// Builds `cargo:rerun-if-changed` lines for the meson build files in `source_dir`.
+///
+/// If `include_sources` is set, all other files are included too. Hidden directories,
+/// like `.git`, are skipped.
+pub(crate) fn rerun_if_changed(source_dir: &Path, include_sources: bool) -> Result<Vec<String>> {
+    let mut files = Vec::new();
+    collect_files(source_dir, include_sources, &mut files)?;
+    files.sort();
+
+    Ok(files
+        .into_iter()
+        .map(|file| format!("cargo:rerun-if-changed={}", file.display()))
+        .collect())
+}
+
+fn collect_files(dir: &Path, include_sources: bool, files: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_name = entry.file_name();
+        if file_name.to_string_lossy().starts_with('.') {
+            continue;
+        }
+
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            collect_files(&path, include_sources, files)?;
+        } else if include_sources
+            || MESON_BUILD_FILES
+                .iter()
+                .any(|build_file| file_name == *build_file)
+        {
+            files.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_library() {
+        assert_eq!(
+            parse_library("libfoo.a"),
+            Some(("foo".to_string(), LinkKind::Static))
+        );
+        assert_eq!(
+            parse_library("libfoo.so"),
+            Some(("foo".to_string(), LinkKind::Dylib))
+        );
+        assert_eq!(
+            parse_library("foo.lib"),
+            Some(("foo".to_string(), LinkKind::Static))
+        );
+        assert_eq!(
+            parse_library("foo.dll"),
+            Some(("foo".to_string(), LinkKind::Dylib))
+        );
+        assert_eq!(parse_library("libfoo.so.1"), None);
+        assert_eq!(parse_library("libfoo.dll.a"), None);
+        assert_eq!(parse_library("foo.pc"), None);
+    }
+
+    #[test]
+    fn test_runtime_library_path() {
+        assert_eq!(runtime_library_path_var("linux"), "LD_LIBRARY_PATH");
+        assert_eq!(runtime_library_path_var("macos"), "DYLD_LIBRARY_PATH");
+        assert_eq!(runtime_library_path_var("windows"), "PATH");
+
+        let lib_dir = tempfile::tempdir().unwrap();
+        let lib_dirs = [lib_dir.path().to_owned(), lib_dir.path().join("missing")];
+        assert_eq!(
+            runtime_library_path("LD_LIBRARY_PATH", &lib_dirs, None).unwrap(),
+            [format!(
+                "cargo:rustc-env=LD_LIBRARY_PATH={}",
+                lib_dir.path().display()
+            )]
+        );
+        assert!(runtime_library_path("PATH", &lib_dirs[1..], None)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_rerun_if_changed() {
+        let source = tempfile::tempdir().unwrap();
+        let root = source.path();
+        std::fs::create_dir_all(root.join("src")).unwrap();
+        std::fs::create_dir_all(root.join(".git")).unwrap();
+
+        for file in [
+            "meson.build",
+            "meson.options",
+            "src/meson.build",
+            "src/main.c",
+            ".git/meson.build",
+        ] {
+            std::fs::write(root.join(file), "").unwrap();
+        }
+
+        let line = |file: &str| format!("cargo:rerun-if-changed={}", root.join(file).display());
+
+        assert_eq!(
+            rerun_if_changed(root, false).unwrap(),
+            [
+                line("meson.build"),
+                line("meson.options"),
+                line("src/meson.build")
+            ]
+        );
+
+        assert_eq!(
+            rerun_if_changed(root, true).unwrap(),
+            [
+                line("meson.build"),
+                line("meson.options"),
+                line("src/main.c"),
+                line("src/meson.build"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_link_directives() {
+        let install = tempfile::tempdir().unwrap();
+        let lib = install.path().join("lib");
+        let lib64 = install.path().join("lib64");
+        std::fs::create_dir_all(lib.join("pkgconfig")).unwrap();
+        std::fs::create_dir_all(&lib64).unwrap();
+
+        for file in ["libfoo.a", "libfoo.so", "libbar.a", "libfoo.so.1"] {
+            std::fs::write(lib.join(file), "").unwrap();
+        }
+        std::fs::write(lib64.join("libbaz.so"), "").unwrap();
+
+        let lib_dirs = [lib.clone(), lib64.clone()];
+
+        assert_eq!(
+            link_directives(&lib_dirs, false).unwrap(),
+            [
+                format!("cargo:rustc-link-search=native={}", lib.display()),
+                format!("cargo:rustc-link-search=native={}", lib64.display()),
+                "cargo:rustc-link-lib=static=bar".to_string(),
+                "cargo:rustc-link-lib=dylib=baz".to_string(),
+                "cargo:rustc-link-lib=dylib=foo".to_string(),
+            ]
+        );
+
+        assert_eq!(
+            link_directives(&lib_dirs, true).unwrap(),
+            [
+                format!("cargo:rustc-link-search=native={}", lib.display()),
+                "cargo:rustc-link-lib=static=bar".to_string(),
+                "cargo:rustc-link-lib=static=foo".to_string(),
+            ]
+        );
+    }
+}
@@ -0,0 +1,472 @@
+//! Running the spawned meson processes.
+
+use std::{
+    fmt,
+    io::{self, BufRead, BufReader, Read, Write},
+    path::{Path, PathBuf},
+    process::{Command, Output, Stdio},
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{Error, Result};
+
+/// Number of trailing stderr lines kept in errors.
+const STDERR_TAIL_LINES: usize = 20;
+
+/// How often a command with timeout is checked for completion.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Receives the output of meson commands line by line.
+#[derive(Clone)]
+pub(crate) struct OutputCallback(pub Arc<dyn Fn(&str) + Send + Sync>);
+
+/// Called with each line of a pipe of a running command, without the line ending.
+type LineHandler = Arc<dyn Fn(&str) + Send + Sync>;
+
+impl fmt::Debug for OutputCallback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("OutputCallback")
+    }
+}
+
+/// Receives the `[done/total]` progress of ninja.
+#[derive(Clone)]
+pub(crate) struct ProgressCallback(pub Arc<dyn Fn(usize, usize) + Send + Sync>);
+
+impl fmt::Debug for ProgressCallback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ProgressCallback")
+    }
+}
+
+/// Parses the `[done/total]` prefix of a ninja status line like `[12/340] Compiling C object`.
+fn parse_progress(line: &str) -> Option<(usize, usize)> {
+    let (progress, _) = line.strip_prefix('[')?.split_once(']')?;
+    let (done, total) = progress.split_once('/')?;
+    Some((done.trim().parse().ok()?, total.trim().parse().ok()?))
+}
+
+/// Runs `command` capturing its output.
+///
+/// Each line of stdout and stderr is forwarded to `callback` or stdout as it arrives, so it is
+/// visible with `cargo build -vv`, and the ninja progress of stdout lines to `progress`. If the
+/// command fails, `failed` creates the error from the exit code and stderr. If the command does
+/// not finish within `timeout`, it is killed.
+pub(crate) fn run(
+    command: &mut Command,
+    timeout: Option<Duration>,
+    callback: Option<&OutputCallback>,
+    progress: Option<&ProgressCallback>,
+    failed: impl FnOnce(i32, String) -> Error,
+) -> Result<Output> {
+    let forward = forward(callback);
+    let stdout_handler = with_progress(forward.clone(), progress);
+    let output = output_with_timeout(command, timeout, Some(stdout_handler), Some(forward))?;
+    check_status(output, failed)
+}
+
+/// Same as [`run`], but waits for `command` without blocking the async executor.
+#[cfg(feature = "async")]
+pub(crate) async fn run_async(
+    command: Command,
+    timeout: Option<Duration>,
+    callback: Option<&OutputCallback>,
+    progress: Option<&ProgressCallback>,
+    failed: impl FnOnce(i32, String) -> Error,
+) -> Result<Output> {
+    let spawn_failed = spawn_error(&command);
+    let mut command = tokio::process::Command::from(command);
+    command.kill_on_drop(true);
+    command.stdin(Stdio::null());
+    command.stdout(Stdio::piped());
+    command.stderr(Stdio::piped());
+
+    let mut child = command.spawn().map_err(spawn_failed)?;
+    let forward = forward(callback);
+    let stdout = read_lines_async(
+        child.stdout.take(),
+        with_progress(forward.clone(), progress),
+    );
+    let stderr = read_lines_async(child.stderr.take(), forward);
+
+    let wait = async {
+        let (stdout, stderr, status) = tokio::join!(stdout, stderr, child.wait());
+        io::Result::Ok(Output {
+            status: status?,
+            stdout: stdout?,
+            stderr: stderr?,
+        })
+    };
+    // The child is killed when dropped on timeout
+    let output = match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, wait)
+            .await
+            .map_err(|_| Error::MesonTimedOut(timeout))??,
+        None => wait.await?,
+    };
+
+    check_status(output, failed)
+}
+
+/// Reads `pipe` until closed, passing each line to `handler`.
+#[cfg(feature = "async")]
+async fn read_lines_async(
+    pipe: Option<impl tokio::io::AsyncRead + Unpin>,
+    handler: LineHandler,
+) -> io::Result<Vec<u8>> {
+    use tokio::io::AsyncBufReadExt;
+
+    let Some(pipe) = pipe else {
+        return Ok(Vec::new());
+    };
+
+    let mut reader = tokio::io::BufReader::new(pipe);
+    let mut buffer = Vec::new();
+    loop {
+        let start = buffer.len();
+        if reader.read_until(b'\n', &mut buffer).await? == 0 {
+            return Ok(buffer);
+        }
+        handle_line(&buffer[start..], &handler);
+    }
+}
+
+/// Forwards lines into `callback`, or stdout without callback.
+fn forward(callback: Option<&OutputCallback>) -> LineHandler {
+    match callback {
+        Some(OutputCallback(callback)) => callback.clone(),
+        None => Arc::new(|line| {
+            // Failing to show the output does not fail the build
+            let _ = writeln!(io::stdout().lock(), "{line}");
+        }),
+    }
+}
+
+/// `handler` also passing the progress of ninja status lines to `progress`.
+fn with_progress(handler: LineHandler, progress: Option<&ProgressCallback>) -> LineHandler {
+    let Some(ProgressCallback(progress)) = progress.cloned() else {
+        return handler;
+    };
+
+    Arc::new(move |line| {
+        handler(line);
+        if let Some((done, total)) = parse_progress(line) {
+            progress(done, total);
+        }
+    })
+}
+
+/// Passes `line` without its line ending to `handler`.
+fn handle_line(line: &[u8], handler: &LineHandler) {
+    let line = String::from_utf8_lossy(line);
+    handler(line.trim_end_matches(['\n', '\r']));
+}
+
+/// The program and arguments of `command`, quoted to be copied into a shell.
+pub(crate) fn command_line(command: &Command) -> String {
+    let mut line = format!("{:?}", command.get_program());
+    for arg in command.get_args() {
+        line.push_str(&format!(" {arg:?}"));
+    }
+    line
+}
+
+/// Runs `command` capturing its output without forwarding it, e.g. for machine-readable output.
+pub(crate) fn output(
+    command: &mut Command,
+    timeout: Option<Duration>,
+    failed: impl FnOnce(i32, String) -> Error,
+) -> Result<Output> {
+    let output = output_with_timeout(command, timeout, None, None)?;
+    check_status(output, failed)
+}
+
+/// Same as [`Command::output`], but kills the child if it runs longer than `timeout`.
+///
+/// The lines of stdout and stderr are passed to the handlers as they arrive.
+fn output_with_timeout(
+    command: &mut Command,
+    timeout: Option<Duration>,
+    stdout_handler: Option<LineHandler>,
+    stderr_handler: Option<LineHandler>,
+) -> Result<Output> {
+    command.stdin(Stdio::null());
+    command.stdout(Stdio::piped());
+    command.stderr(Stdio::piped());
+
+    let start = Instant::now();
+    let mut child = command.spawn().map_err(spawn_error(command))?;
+
+    // Pipes are drained concurrently, so the child never blocks on a full pipe
+    let stdout = child
+        .stdout
+        .take()
+        .map(|pipe| read_in_background(pipe, stdout_handler));
+    let stderr = child
+        .stderr
+        .take()
+        .map(|pipe| read_in_background(pipe, stderr_handler));
+
+    let Some(timeout) = timeout else {
+        let status = child.wait()?;
+        return collect_output(status, stdout, stderr);
+    };
+
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+
+        let elapsed = start.elapsed();
+        if elapsed >= timeout {
+            child.kill()?;
+            child.wait()?;
+            // Readers are not joined: grandchildren may still hold the pipes open
+            return Err(Error::MesonTimedOut(timeout));
+        }
+
+        thread::sleep(POLL_INTERVAL.min(timeout - elapsed));
+    };
+
+    collect_output(status, stdout, stderr)
+}
+
+type Reader = thread::JoinHandle<io::Result<Vec<u8>>>;
+
+/// The output of an exited child, once its pipes are fully read.
+fn collect_output(
+    status: std::process::ExitStatus,
+    stdout: Option<Reader>,
+    stderr: Option<Reader>,
+) -> Result<Output> {
+    let join = |reader: Option<Reader>| match reader {
+        Some(reader) => reader.join().expect("pipe reader panicked"),
+        None => Ok(Vec::new()),
+    };
+
+    Ok(Output {
+        status,
+        stdout: join(stdout)?,
+        stderr: join(stderr)?,
+    })
+}
+
+/// Converts the error of starting `command` into [`Error::CommandSpawn`] naming its program.
+fn spawn_error(command: &Command) -> impl FnOnce(io::Error) -> Error {
+    let program = PathBuf::from(command.get_program());
+    move |source| Error::CommandSpawn { program, source }
+}
+
+/// Reads `pipe` until closed, passing each line to `handler` if any.
+fn read_in_background(
+    mut pipe: impl Read + Send + 'static,
+    handler: Option<LineHandler>,
+) -> Reader {
+    thread::spawn(move || {
+        let mut buffer = Vec::new();
+        let Some(handler) = handler else {
+            pipe.read_to_end(&mut buffer)?;
+            return Ok(buffer);
+        };
+
+        let mut reader = BufReader::new(pipe);
+        loop {
+            let start = buffer.len();
+            if reader.read_until(b'\n', &mut buffer)? == 0 {
+                return Ok(buffer);
+            }
+            handle_line(&buffer[start..], &handler);
+        }
+    })
+}
+
+fn check_status(output: Output, failed: impl FnOnce(i32, String) -> Error) -> Result<Output> {
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+        return match output.status.code() {
+            Some(code) => Err(failed(code, stderr)),
+            None => Err(Error::MesonExitedBySignal),
+        };
+    }
+
+    Ok(output)
+}
+
+/// The last lines of `stderr`, which usually contain the reason of failure.
+pub(crate) fn stderr_tail(stderr: &str) -> String {
+    let lines: Vec<&str> = stderr.trim_end().lines().collect();
+    let start = lines.len().saturating_sub(STDERR_TAIL_LINES);
+    lines[start..].join("\n")
+}
+
+/// Points to the meson log at `log_path` for details, empty without log.
+pub(crate) fn log_hint(log_path: Option<&Path>) -> String {
+    match log_path {
+        Some(log_path) => format!("\nsee {} for details", log_path.display()),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_command_line() {
+        let mut command = Command::new("meson");
+        command.args(["setup", "--prefix", "/out dir/install"]);
+        assert_eq!(
+            command_line(&command),
+            r#""meson" "setup" "--prefix" "/out dir/install""#
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_output_with_timeout() {
+        let mut command = Command::new("sh");
+        command.args(["-c", "echo out; echo err >&2"]);
+
+        let output =
+            output_with_timeout(&mut command, Some(Duration::from_secs(10)), None, None).unwrap();
+        assert!(output.status.success());
+        assert_eq!(output.stdout, b"out\n");
+        assert_eq!(output.stderr, b"err\n");
+    }
+
+    /// A script printing `first`, then `second` to stderr and `third` once the `n`-th line was
+    /// received, created as a marker file by the callback. Fails if a line is not received
+    /// before the script exits.
+    #[cfg(unix)]
+    fn interleaved_output(
+        dir: &Path,
+    ) -> (Command, OutputCallback, Arc<std::sync::Mutex<Vec<String>>>) {
+        let wait = |marker: &str| {
+            format!(
+                "i=0; while [ ! -e {marker} ]; do i=$((i+1)); [ $i -gt 500 ] && exit 1; sleep 0.01; done"
+            )
+        };
+        let mut command = Command::new("sh");
+        command.current_dir(dir).args([
+            "-c",
+            &format!(
+                "echo first; {}; echo second >&2; {}; echo third",
+                wait("1"),
+                wait("2")
+            ),
+        ]);
+
+        let lines = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let received = lines.clone();
+        let dir = dir.to_owned();
+        let callback = OutputCallback(Arc::new(move |line: &str| {
+            let mut lines = received.lock().unwrap();
+            lines.push(line.to_string());
+            std::fs::write(dir.join(lines.len().to_string()), "").unwrap();
+        }));
+
+        (command, callback, lines)
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_forwards_lines_live() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut command, callback, lines) = interleaved_output(dir.path());
+
+        let output = run(
+            &mut command,
+            None,
+            Some(&callback),
+            None,
+            |_, _| unreachable!(),
+        )
+        .unwrap();
+        assert_eq!(*lines.lock().unwrap(), ["first", "second", "third"]);
+        assert_eq!(output.stdout, b"first\nthird\n");
+        assert_eq!(output.stderr, b"second\n");
+    }
+
+    #[cfg(all(unix, feature = "async"))]
+    #[tokio::test]
+    async fn test_run_async_forwards_lines_live() {
+        let dir = tempfile::tempdir().unwrap();
+        let (command, callback, lines) = interleaved_output(dir.path());
+
+        run_async(command, None, Some(&callback), None, |_, _| unreachable!())
+            .await
+            .unwrap();
+        assert_eq!(*lines.lock().unwrap(), ["first", "second", "third"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_spawn_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let program = dir.path().join("meson");
+        std::fs::write(&program, "#!/bin/sh\n").unwrap();
+
+        let err = output(&mut Command::new(&program), None, |_, _| unreachable!()).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::CommandSpawn { program: ref path, ref source }
+                if *path == program && source.kind() == io::ErrorKind::PermissionDenied
+        ));
+        assert!(err.to_string().contains(&program.display().to_string()));
+    }
+
+    #[test]
+    fn test_parse_progress() {
+        assert_eq!(parse_progress("ninja: Entering directory `build'"), None);
+        assert_eq!(
+            parse_progress("[1/3] Compiling C object answer.p/answer.c.o"),
+            Some((1, 3))
+        );
+        assert_eq!(parse_progress("[ 12/340] Linking target"), Some((12, 340)));
+        assert_eq!(parse_progress("[x/3] Not a progress line"), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_reports_progress_live() {
+        let dir = tempfile::tempdir().unwrap();
+        // The second step only starts once the first one was reported
+        let mut command = Command::new("sh");
+        command.current_dir(dir.path()).args([
+            "-c",
+            "echo '[1/2] Compiling'; i=0; while [ ! -e 1 ]; do i=$((i+1)); [ $i -gt 500 ] && exit 1; sleep 0.01; done; echo '[2/2] Linking'",
+        ]);
+
+        let calls = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let collected = calls.clone();
+        let dir_path = dir.path().to_owned();
+        let progress = ProgressCallback(Arc::new(move |done, total| {
+            collected.lock().unwrap().push((done, total));
+            std::fs::write(dir_path.join(done.to_string()), "").unwrap();
+        }));
+        let callback = OutputCallback(Arc::new(|_| {}));
+
+        run(
+            &mut command,
+            None,
+            Some(&callback),
+            Some(&progress),
+            |_, _| unreachable!(),
+        )
+        .unwrap();
+        assert_eq!(*calls.lock().unwrap(), [(1, 2), (2, 2)]);
+    }
+
+    #[test]
+    fn test_stderr_tail() {
+        assert_eq!(stderr_tail(""), "");
+        assert_eq!(stderr_tail("first\nsecond\n"), "first\nsecond");
+
+        let long: String = (0..100).map(|line| format!("{line}\n")).collect();
+        let tail = stderr_tail(&long);
+        assert_eq!(tail.lines().count(), STDERR_TAIL_LINES);
+        assert_eq!(tail.lines().last(), Some("99"));
+    }
+}
@@ -0,0 +1,62 @@
+//! Compiler caches wrapping the C and C++ compilers.
+
+use std::env;
+
+/// A compiler cache wrapping the C and C++ compilers, like `ccache gcc`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum CompilerCache {
+    #[default]
+    None,
+    Ccache,
+    Sccache,
+    /// Any other wrapper program, optionally followed by its arguments.
+    Custom(String),
+}
+
+impl CompilerCache {
+    /// The wrapper command prepended to the compilers, `None` if disabled.
+    pub fn command(&self) -> Option<&str> {
+        match self {
+            CompilerCache::None => None,
+            CompilerCache::Ccache => Some("ccache"),
+            CompilerCache::Sccache => Some("sccache"),
+            CompilerCache::Custom(command) => Some(command),
+        }
+    }
+
+    /// `compiler` prefixed by the wrapper command.
+    pub(crate) fn wrap(&self, compiler: &str) -> String {
+        match self.command() {
+            Some(command) => format!("{command} {compiler}"),
+            None => compiler.to_string(),
+        }
+    }
+}
+
+/// Whether `program` is found in one of the `PATH` directories.
+pub(crate) fn in_path(program: &str) -> bool {
+    let Some(paths) = env::var_os("PATH") else {
+        return false;
+    };
+
+    env::split_paths(&paths).any(|dir| {
+        let candidate = dir.join(program);
+        candidate.is_file() || (cfg!(windows) && candidate.with_extension("exe").is_file())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wrap() {
+        assert_eq!(CompilerCache::None.wrap("gcc"), "gcc");
+        assert_eq!(CompilerCache::Ccache.wrap("gcc"), "ccache gcc");
+        assert_eq!(CompilerCache::Sccache.wrap("clang"), "sccache clang");
+        assert_eq!(
+            CompilerCache::Custom("buildcache -v".to_string()).wrap("cc"),
+            "buildcache -v cc"
+        );
+    }
+}
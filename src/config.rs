@@ -1,14 +1,33 @@
+#[cfg(feature = "async")]
+mod async_build;
+mod options_file;
+#[cfg(feature = "serde")]
+mod settings;
+
 use std::{
-    collections::HashMap,
+    collections::{hash_map::DefaultHasher, BTreeMap, HashMap},
     env,
-    ffi::OsString,
+    ffi::{OsStr, OsString},
+    fmt,
+    hash::{Hash, Hasher},
+    io,
     path::{Path, PathBuf},
-    process::Command,
+    process::{Command, Output},
+    sync::{Arc, Mutex, OnceLock},
+    time::Duration,
 };
 
-use semver::Version;
+use semver::{Version, VersionReq};
 
-use crate::{Error, Result};
+use crate::{
+    cargo,
+    command::{self, OutputCallback, ProgressCallback},
+    compiler_cache, introspect,
+    lock::FileLock,
+    machine_file, options, test_log, Backend, BuildOutcome, CompilerCache, DeclaredDependency,
+    Dependency, Error, Feature, LibraryKind, LtoMode, Result, Sanitizer, Target, TestResult,
+    UnityMode, WrapMode,
+};
 
 /// The configuration for the Meson build containing executable to run to build the project.
 /// and options to pass into it.
@@ -16,37 +35,292 @@ use crate::{Error, Result};
 #[derive(Debug, Clone)]
 pub struct Config {
     meson_path: PathBuf,
+    /// Arguments selecting meson when `meson_path` is an interpreter, like `-m mesonbuild`.
+    meson_args: Vec<OsString>,
     meson_version: Version,
 
-    native_file: Option<PathBuf>,
-    cross_file: Option<PathBuf>,
+    /// Machine files passed in order, meson merges them.
+    native_files: Vec<PathBuf>,
+    cross_files: Vec<PathBuf>,
+    options_file: Option<PathBuf>,
     out_path: Option<PathBuf>,
 
-    options: HashMap<String, String>,
+    /// Names of the build and install directories inside the output path.
+    build_dir_name: String,
+    /// Build directory configured by another tool, used instead of the one in the output path.
+    external_build_dir: Option<PathBuf>,
+    install_dir_name: String,
+
+    /// Kept ordered so the generated `-D` arguments are deterministic.
+    options: BTreeMap<String, String>,
+
+    /// Install layout relative to the prefix: see `--libdir` and others in the Meson documentation.
+    libdir: Option<PathBuf>,
+    bindir: Option<PathBuf>,
+    includedir: Option<PathBuf>,
+    datadir: Option<PathBuf>,
+
+    /// Arguments accumulated into `c_args`, `cpp_args` and `*_link_args` options.
+    c_args: Vec<String>,
+    cpp_args: Vec<String>,
+    link_args: Vec<String>,
+
+    /// Paths accumulated into the `cmake_prefix_path` option.
+    cmake_prefix_paths: Vec<String>,
 
     /// Build profile: see `--buildtype` in the Meson documentation.
     profile: Option<String>,
+
+    backend: Option<Backend>,
+
+    wrap_mode: Option<WrapMode>,
+    vsenv: bool,
+
+    /// Time limit for each spawned meson command.
+    timeout: Option<Duration>,
+
+    /// Number of parallel jobs: see `-j` of `meson compile`.
+    jobs: Option<usize>,
+
+    /// Print the commands run by the backend: see `-v` of `meson compile`.
+    verbose: bool,
+    quiet: bool,
+
+    /// Targets built by the compile step, all if empty.
+    targets: Vec<String>,
+
+    /// Subprojects installed by the install step, all if `None`.
+    subproject_filter: Option<Vec<String>>,
+
+    /// Print meson commands instead of running them.
+    dry_run: bool,
+
+    /// Receives the output of meson commands instead of stdout.
+    output_callback: Option<OutputCallback>,
+    progress_callback: Option<ProgressCallback>,
+
+    /// Raw arguments appended to `meson setup`.
+    extra_setup_args: Vec<OsString>,
+
+    /// Re-run `meson setup` even if the build directory is already configured.
+    reconfigure: bool,
+
+    /// Wipe the configured build directory on setup: see `--wipe` of `meson setup`.
+    wipe: bool,
+
+    /// Whether [`Config::build`] runs `meson install` after compiling.
+    install: bool,
+
+    /// Staging directory for `meson install`, passed as `DESTDIR`.
+    destdir: Option<PathBuf>,
+    prefix: Option<PathBuf>,
+
+    /// Strip installed binaries: see `--strip` of `meson install`.
+    strip: bool,
+
+    /// Install only files with these tags: see `--tags` of `meson install`.
+    install_tags: Vec<String>,
+
+    /// Environment overrides for spawned meson commands, `None` removes the variable.
+    env: BTreeMap<String, Option<String>>,
+
+    /// Wrapper prepended to `CC` and `CXX` on setup.
+    compiler_cache: CompilerCache,
+
+    /// Ninja exported to meson commands as `NINJA`.
+    ninja_path: Option<PathBuf>,
+    package_cache_dir: Option<PathBuf>,
+
+    /// Reads the variables provided by Cargo, the process environment if `None`.
+    env_lookup: Option<EnvLookup>,
+
+    /// Receives the lines printed for Cargo, stdout if `None`.
+    printer: Option<Printer>,
+}
+
+type LookupFn = dyn Fn(&str) -> Option<OsString> + Send + Sync;
+
+/// Replacement of the process environment, used by the tests.
+#[derive(Clone)]
+struct EnvLookup(Arc<LookupFn>);
+
+impl fmt::Debug for EnvLookup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("EnvLookup")
+    }
+}
+
+type PrintFn = dyn Fn(&str) + Send + Sync;
+
+/// Replacement of stdout for the lines printed for Cargo, used by the tests.
+#[derive(Clone)]
+struct Printer(Arc<PrintFn>);
+
+impl fmt::Debug for Printer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Printer")
+    }
 }
 
 impl Config {
     /// Find the system-wide Meson installation.
     ///
+    /// The version of each found meson is probed once per process.
     /// See [`crate::find_meson`]
     pub fn find_system_meson() -> Result<Self> {
-        let meson = Self::find_meson_in_system()?;
-        let version = Self::get_version_of_meson(&meson)?;
-        Ok(Self {
-            meson_path: meson,
-            meson_version: version,
-
-            native_file: None,
-            cross_file: None,
+        Self::find_first_meson(Self::meson_candidates())
+    }
+
+    /// Uses the first of `candidates` which reports its version, caching the result.
+    fn find_first_meson(candidates: Vec<Launcher>) -> Result<Self> {
+        let cache = version_cache();
+        if let Some((launcher, version)) = cache.lock().unwrap().get(&candidates) {
+            let (meson_path, meson_args) = launcher.clone();
+            return Ok(Self::new(meson_path, version.clone()).with_meson_args(meson_args));
+        }
+
+        let mut first_err = None;
+        for (meson_path, meson_args) in candidates.iter().cloned() {
+            match Self::probe_meson(meson_path, meson_args) {
+                Ok(config) => {
+                    let launcher = (config.meson_path.clone(), config.meson_args.clone());
+                    let version = config.meson_version.clone();
+                    cache
+                        .lock()
+                        .unwrap()
+                        .insert(candidates, (launcher, version));
+                    return Ok(config);
+                }
+                Err(err) => {
+                    first_err.get_or_insert(err);
+                }
+            }
+        }
+
+        Err(first_err.unwrap_or(Error::MesonNotFound {
+            tried: PathBuf::from("meson"),
+        }))
+    }
+
+    /// Use the Meson installation at `path`.
+    ///
+    /// The path is validated by running `meson --version`.
+    pub fn with_meson_path(path: impl Into<PathBuf>) -> Result<Self> {
+        Self::probe_meson(path.into(), Vec::new())
+    }
+
+    /// Use meson installed as the `mesonbuild` module of the `python` interpreter.
+    ///
+    /// Every meson command is run as `<python> -m mesonbuild <subcommand> ...`.
+    pub fn with_python_module(python: &Path) -> Result<Self> {
+        Self::probe_meson(python.to_owned(), mesonbuild_module())
+    }
+
+    /// Runs `meson_path` with `meson_args` to get the meson version.
+    fn probe_meson(meson_path: PathBuf, meson_args: Vec<OsString>) -> Result<Self> {
+        let meson_version = match Self::get_version_of_meson(&meson_path, &meson_args) {
+            Ok(version) => version,
+            Err(Error::CommandSpawn { source, .. }) if source.kind() == io::ErrorKind::NotFound => {
+                return Err(Error::MesonNotFound { tried: meson_path })
+            }
+            Err(Error::CommandSpawn { source, .. })
+                if source.kind() == io::ErrorKind::PermissionDenied =>
+            {
+                return Err(Error::MesonNotExecutable(meson_path))
+            }
+            Err(err) => return Err(err),
+        };
+
+        Ok(Self::new(meson_path, meson_version).with_meson_args(meson_args))
+    }
+
+    /// Sets the arguments passed before the meson subcommand, e.g. `-m mesonbuild` for python.
+    fn with_meson_args(mut self, meson_args: Vec<OsString>) -> Self {
+        self.meson_args = meson_args;
+        self
+    }
+
+    /// Use the Meson installation at `meson_path` of the given version with default settings.
+    ///
+    /// Unlike [`Config::with_meson_path`], meson is not run, so the version is not checked.
+    /// Useful to test argument generation without a meson installation.
+    pub fn new(meson_path: PathBuf, meson_version: Version) -> Self {
+        Self {
+            meson_path,
+            meson_args: Vec::new(),
+            meson_version,
+
+            native_files: Vec::new(),
+            cross_files: Vec::new(),
+            options_file: None,
             out_path: None,
 
-            options: HashMap::new(),
+            build_dir_name: "build".to_string(),
+            external_build_dir: None,
+            install_dir_name: "install".to_string(),
+
+            options: BTreeMap::new(),
+
+            libdir: None,
+            bindir: None,
+            includedir: None,
+            datadir: None,
+
+            c_args: Vec::new(),
+            cpp_args: Vec::new(),
+            link_args: Vec::new(),
+
+            cmake_prefix_paths: Vec::new(),
 
             profile: None,
-        })
+
+            backend: None,
+
+            wrap_mode: None,
+            vsenv: false,
+
+            timeout: None,
+
+            jobs: None,
+
+            verbose: false,
+            quiet: false,
+
+            targets: Vec::new(),
+
+            subproject_filter: None,
+
+            dry_run: false,
+
+            output_callback: None,
+            progress_callback: None,
+
+            extra_setup_args: Vec::new(),
+
+            reconfigure: false,
+            wipe: false,
+
+            install: true,
+            strip: false,
+            install_tags: Vec::new(),
+
+            env: BTreeMap::new(),
+
+            compiler_cache: CompilerCache::None,
+
+            ninja_path: None,
+            package_cache_dir: None,
+            destdir: None,
+            prefix: None,
+
+            env_lookup: None,
+            printer: None,
+        }
+    }
+
+    /// The meson program the commands are run with, as resolved by [`Config::find_system_meson`].
+    pub fn meson_path(&self) -> &Path {
+        &self.meson_path
     }
 
     /// Gets meson version
@@ -54,225 +328,4618 @@ impl Config {
         format!("{}", self.meson_version)
     }
 
+    /// Gets meson version as parsed from `meson --version`.
+    pub fn meson_version_parsed(&self) -> &Version {
+        &self.meson_version
+    }
+
+    /// Checks that the meson version satisfies `req`, e.g. `">=1.1, <2"`.
+    pub fn require_version(&self, req: &str) -> Result<()> {
+        let required = VersionReq::parse(req).map_err(Error::InvalidVersionRequirement)?;
+        if !required.matches(&self.meson_version) {
+            return Err(Error::MesonVersionTooOld {
+                found: self.meson_version.clone(),
+                required,
+            });
+        }
+
+        Ok(())
+    }
+
     /// Sets the native file path for meson build
+    ///
+    /// Replaces the files added by [`Config::add_native_file`].
     pub fn set_native_file(&mut self, file: &Path) {
-        self.native_file = Some(file.to_owned());
+        self.native_files = vec![file.to_owned()];
+    }
+
+    /// Adds a native file layered over the previous ones, e.g. project-specific overrides of a
+    /// base machine file.
+    pub fn add_native_file(&mut self, file: &Path) {
+        self.native_files.push(file.to_owned());
     }
 
     /// Sets the cross file path for meson build
+    ///
+    /// Replaces the files added by [`Config::add_cross_file`].
     pub fn set_cross_file(&mut self, file: &Path) {
-        self.cross_file = Some(file.to_owned());
+        self.cross_files = vec![file.to_owned()];
+    }
+
+    /// Adds a cross file layered over the previous ones.
+    pub fn add_cross_file(&mut self, file: &Path) {
+        self.cross_files.push(file.to_owned());
+    }
+
+    /// Uses `file` instead of the options file of the source tree, e.g. a patched
+    /// `meson_options.txt` of a third-party project.
+    ///
+    /// The source tree is left untouched: meson is configured from a mirror in
+    /// [`Config::out_path`] linking the entries of the source tree next to a copy of `file`.
+    ///
+    /// Changing `file` or its contents runs `meson setup` again.
+    pub fn set_options_file(&mut self, file: &Path) {
+        self.options_file = Some(file.to_owned());
+    }
+
+    /// Generates a native file from `CC`, `CXX`, `AR` and `STRIP` environment variables
+    /// and uses it for the build.
+    ///
+    /// Variables are looked up as in the `cc` crate, e.g. `CC_<target>`, `HOST_CC`, then `CC`.
+    /// The file is written as `meson-native.ini` into [`Config::out_path`].
+    pub fn generate_native_file_from_env(&mut self) -> Result<()> {
+        let target = self.var("TARGET");
+        let binaries = machine_file::binaries(target.as_deref(), "HOST", |var| self.var(var));
+
+        let out_path = self.out_path()?;
+        std::fs::create_dir_all(&out_path)?;
+
+        let native_file = out_path.join("meson-native.ini");
+        std::fs::write(&native_file, machine_file::section("binaries", &binaries))?;
+
+        self.set_native_file(&native_file);
+        Ok(())
+    }
+
+    /// Generates a cross file describing the `TARGET` provided by Cargo and uses it for the build.
+    ///
+    /// The `[host_machine]` section is derived from the `CARGO_CFG_TARGET_*` variables set by
+    /// Cargo, or from the target triple if they are not set. Cross compilers are
+    /// looked up as in the `cc` crate, e.g. `CC_<target>`, `TARGET_CC`, then `CC`.
+    /// The file is written as `meson-cross.ini` into [`Config::out_path`].
+    pub fn generate_cross_file_for_target(&mut self) -> Result<()> {
+        let target = self.var("TARGET").ok_or(Error::TargetNotSet)?;
+        let host_machine = machine_file::HostMachine::from_cargo_cfg(&target, |var| self.var(var))
+            .or_else(|| machine_file::HostMachine::from_triple(&target))
+            .ok_or_else(|| Error::UnsupportedTarget(target.clone()))?;
+        let binaries = machine_file::binaries(Some(&target), "TARGET", |var| self.var(var));
+
+        let out_path = self.out_path()?;
+        std::fs::create_dir_all(&out_path)?;
+
+        let cross_file = out_path.join("meson-cross.ini");
+        let contents = format!(
+            "{}\n{}",
+            machine_file::section("binaries", &binaries),
+            host_machine.section()
+        );
+        std::fs::write(&cross_file, contents)?;
+
+        self.set_cross_file(&cross_file);
+        Ok(())
     }
 
     /// Sets the output path for meson build
     ///
-    /// There will be a `build` folder in the output path.
+    /// There will be a `build` folder in the output path, see [`Config::set_build_dir_name`].
     pub fn set_out_path(&mut self, path: &Path) {
         self.out_path = Some(path.to_owned());
     }
 
-    /// Sets the meson build option
+    /// Sets the name of the build directory inside the output path, `build` by default.
     ///
-    /// If options exists, it will be overwritten.
-    pub fn set_option(&mut self, key: &str, value: &str) {
-        self.options.insert(key.to_string(), value.to_string());
+    /// Useful to keep several meson projects built from one `OUT_DIR` apart.
+    pub fn set_build_dir_name(&mut self, name: &str) {
+        self.build_dir_name = name.to_string();
     }
 
-    /// Set the meson build profile passed by `--buildtype` argument
-    pub fn set_profile(&mut self, profile: &str) {
-        self.profile = Some(profile.to_string());
+    /// Uses `dir` configured beforehand, e.g. by an external script, as the build directory.
+    ///
+    /// `meson setup` never runs for it: building fails with [`Error::NotConfigured`] if `dir`
+    /// is not configured. The install directory stays in the output path.
+    pub fn set_external_build_dir(&mut self, dir: &Path) {
+        self.external_build_dir = Some(dir.to_owned());
     }
 
-    /// Get the path of the build directory.
-    pub fn build_dir(&self) -> PathBuf {
-        self.out_path().join("build")
+    /// Sets the name of the install directory inside the output path, `install` by default.
+    pub fn set_install_dir_name(&mut self, name: &str) {
+        self.install_dir_name = name.to_string();
     }
 
-    /// Get the path of the install directory.
-    pub fn install_dir(&self) -> PathBuf {
-        self.out_path().join("install")
+    /// Sets whether [`Config::build`] installs the project, enabled by default.
+    ///
+    /// If disabled, the compiled artifacts are only available in [`Config::build_dir`].
+    pub fn set_install(&mut self, enabled: bool) {
+        self.install = enabled;
     }
 
-    fn is_configured(&self) -> bool {
-        self.build_dir().join("build.ninja").exists()
+    /// Sets the staging directory for the install step using the `DESTDIR` environment variable.
+    ///
+    /// The `--prefix` is not changed, so files are installed into `DESTDIR` followed by the
+    /// prefix path, while paths embedded into the installed files still refer to the prefix.
+    pub fn set_destdir(&mut self, dir: &Path) {
+        self.destdir = Some(dir.to_owned());
     }
 
-    fn configure(&self, source_dir: &Path) -> Result<()> {
-        if self.is_configured() {
-            return Ok(());
-        }
+    /// Sets the `--prefix` embedded into the installed files, e.g. `/usr`, instead of
+    /// [`Config::install_dir`].
+    ///
+    /// Combine it with [`Config::set_destdir`] to stage the install elsewhere. The `cargo:`
+    /// directives still refer to [`Config::install_dir`].
+    pub fn set_prefix(&mut self, prefix: &Path) {
+        self.prefix = Some(prefix.to_owned());
+    }
 
-        let build_dir = self.build_dir();
-        std::fs::create_dir_all(&build_dir)?;
+    /// Sets whether binaries are stripped when installed, disabled by default.
+    ///
+    /// Only the installed artifacts are stripped, the build tree is left untouched.
+    pub fn set_strip(&mut self, enabled: bool) {
+        self.strip = enabled;
+    }
 
-        let mut args: Vec<String> = vec!["setup".to_string()];
+    /// Sets the install tags, e.g. `devel` for headers and libraries to link with.
+    ///
+    /// Everything is installed if empty, which is the default.
+    pub fn set_install_tags(&mut self, tags: &[&str]) {
+        self.install_tags = tags.iter().map(|tag| tag.to_string()).collect();
+    }
 
-        let profile = self.profile();
-        if !profile.is_empty() {
-            args.extend(["--buildtype".to_string(), profile.to_string()]);
-        } else {
-            println!("cargo:info=profile is empty, ignoring profile option.");
+    /// Sets an environment variable for all spawned meson commands.
+    ///
+    /// The rest of the environment is inherited from the build script.
+    pub fn set_env(&mut self, key: &str, value: &str) {
+        self.env.insert(key.to_string(), Some(value.to_string()));
+    }
+
+    /// Removes an environment variable from all spawned meson commands.
+    pub fn remove_env(&mut self, key: &str) {
+        self.env.insert(key.to_string(), None);
+    }
+
+    /// Sets a compiler cache wrapping the C and C++ compilers.
+    ///
+    /// The wrapper is prepended to `CC` and `CXX` passed to `meson setup`, which default to
+    /// `cc` and `c++` if not set. Compilers given in machine files are not wrapped.
+    /// A warning is printed if `ccache` or `sccache` is not found in `PATH`.
+    pub fn set_compiler_cache(&mut self, cache: CompilerCache) {
+        if let CompilerCache::Ccache | CompilerCache::Sccache = cache {
+            let program = cache.command().unwrap_or_default();
+            if !compiler_cache::in_path(program) {
+                self.print(&format!("cargo:warning={program} is not found in PATH"));
+            }
         }
+        self.compiler_cache = cache;
+    }
 
-        let options = self
-            .options
-            .iter()
-            .map(|(key, value)| format!("-D{}={}", key, value));
+    /// Sets the ninja executable used by meson, exported as the `NINJA` environment variable.
+    ///
+    /// See [`crate::find_ninja`] to resolve it the same way as meson.
+    pub fn set_ninja_path(&mut self, path: &Path) {
+        self.ninja_path = Some(path.to_owned());
+    }
 
-        args.extend(options);
+    /// Sets the directory with pre-downloaded subprojects, exported as the
+    /// `MESON_PACKAGE_CACHE_DIR` environment variable.
+    ///
+    /// Meson looks there for the archives of wrap files and, since 1.3, for already extracted
+    /// subprojects before downloading them. Together with [`WrapMode::NoDownload`] the build
+    /// is fully offline and pinned.
+    pub fn set_subprojects_dir(&mut self, dir: &Path) {
+        self.package_cache_dir = Some(dir.to_owned());
+    }
 
-        // Switch to OsString when dealing with paths
-        let mut os_args: Vec<OsString> = args.into_iter().map(|x| OsString::from(x)).collect();
+    /// Sets the library directory relative to the prefix, e.g. `lib` instead of the
+    /// distribution default `lib64`.
+    ///
+    /// [`Config::emit_cargo_metadata`] and [`Config::emit_pkg_config`] search only this
+    /// directory when set.
+    pub fn set_libdir(&mut self, dir: &Path) {
+        self.libdir = Some(dir.to_owned());
+    }
 
-        // Native file
-        if let Some(ref native_file) = self.native_file {
-            os_args.extend([OsString::from("--native-file"), native_file.into()]);
-        }
+    /// Sets the executable directory relative to the prefix
+    pub fn set_bindir(&mut self, dir: &Path) {
+        self.bindir = Some(dir.to_owned());
+    }
 
-        // Cross file
-        if let Some(ref cross_file) = self.cross_file {
-            os_args.extend([OsString::from("--cross-file"), cross_file.into()]);
-        }
+    /// Sets the header directory relative to the prefix
+    pub fn set_includedir(&mut self, dir: &Path) {
+        self.includedir = Some(dir.to_owned());
+    }
+
+    /// Sets the data directory relative to the prefix
+    pub fn set_datadir(&mut self, dir: &Path) {
+        self.datadir = Some(dir.to_owned());
+    }
 
-        // Install prefix
-        os_args.extend([OsString::from("--prefix"), self.install_dir().into()]);
+    /// Sets the meson build option
+    ///
+    /// If options exists, it will be overwritten.
+    pub fn set_option(&mut self, key: &str, value: &str) {
+        self.options.insert(key.to_string(), value.to_string());
+    }
 
-        // Finally, source directory
-        os_args.extend([source_dir.as_os_str().to_os_string()]);
+    /// Sets the meson build option only if it is not set yet, e.g. for defaults of a layered
+    /// config.
+    pub fn set_option_default(&mut self, key: &str, value: &str) {
+        self.options
+            .entry(key.to_string())
+            .or_insert_with(|| value.to_string());
+    }
 
-        let mut command = Command::new(self.meson_path.clone());
-        command.current_dir(source_dir);
-        command.args(os_args);
+    /// Removes the meson build option, returning its value if it was set.
+    pub fn remove_option(&mut self, key: &str) -> Option<String> {
+        self.options.remove(key)
+    }
 
-        let status = command.status()?;
-        if !status.success() {
-            return match status.code() {
-                Some(code) => Err(Error::MesonConfiguredUnsuccessfully(code)),
-                None => Err(Error::MesonExitedBySignal),
-            };
-        }
+    /// Removes all meson build options set by `set_option` and its typed variants.
+    ///
+    /// Arguments added by [`Config::add_c_arg`] and similar are kept.
+    pub fn clear_options(&mut self) {
+        self.options.clear();
+    }
 
-        Ok(())
+    /// Iterates over the meson build options set on this config, ordered by name.
+    pub fn options(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.options
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
     }
 
-    /// Start a new build process for the meson project in `source_dir`
-    pub fn build(self, source_dir: &Path) -> Result<()> {
-        self.configure(source_dir)?;
+    /// Gets the value of a meson build option set on this config.
+    pub fn get_option(&self, key: &str) -> Option<&str> {
+        self.options.get(key).map(String::as_str)
+    }
 
-        let out_path = self.out_path();
-        let build_dir = out_path.join("build");
-        let install_dir = out_path.join("install");
+    /// Sets the meson boolean build option
+    pub fn set_option_bool(&mut self, key: &str, value: bool) {
+        self.set_option(key, if value { "true" } else { "false" });
+    }
 
-        std::fs::create_dir_all(&build_dir)?;
-        std::fs::create_dir_all(&install_dir)?;
+    /// Sets the meson feature build option
+    pub fn set_option_feature(&mut self, key: &str, value: Feature) {
+        self.set_option(key, value.as_str());
+    }
 
-        let mut build_command = Command::new(self.meson_path.clone());
-        build_command.current_dir(source_dir);
-        build_command.arg("build");
-        build_command.arg("-C");
-        build_command.arg(&build_dir);
+    /// Sets the sanitizer instrumenting the meson project using the `b_sanitize` option.
+    ///
+    /// Use the same sanitizer as the Rust code, e.g. `-Zsanitizer=address` with
+    /// [`Sanitizer::Address`]. Visual Studio backends support only the address sanitizer.
+    pub fn set_sanitizer(&mut self, sanitizer: Sanitizer) {
+        self.set_option("b_sanitize", sanitizer.as_str());
+    }
 
-        let status = build_command.status()?;
-        if !status.success() {
-            return match status.code() {
-                Some(code) => Err(Error::MesonBuildUnsuccessfully(code)),
-                None => Err(Error::MesonExitedBySignal),
-            };
-        }
+    /// Sets whether the meson project is built with link-time optimization, using the `b_lto`
+    /// option.
+    ///
+    /// Independent of the buildtype.
+    pub fn set_lto(&mut self, enabled: bool) {
+        self.set_option_bool("b_lto", enabled);
+    }
 
-        let mut install_command = Command::new(self.meson_path.clone());
-        install_command.current_dir(source_dir);
-        install_command.arg("install");
-        install_command.arg("-C");
-        install_command.arg(&build_dir);
+    /// Sets the link-time optimization mode using the `b_lto_mode` option.
+    ///
+    /// Only used if LTO is enabled by [`Config::set_lto`].
+    pub fn set_lto_mode(&mut self, mode: LtoMode) {
+        self.set_option("b_lto_mode", mode.as_str());
+    }
 
-        let status = install_command.status()?;
-        if !status.success() {
-            return match status.code() {
-                Some(code) => Err(Error::MesonBuildUnsuccessfully(code)),
-                None => Err(Error::MesonExitedBySignal),
-            };
-        }
+    /// Sets whether the meson project is instrumented for coverage, using the `b_coverage`
+    /// option.
+    ///
+    /// See [`Config::coverage_report`] to generate the reports.
+    pub fn set_coverage(&mut self, enabled: bool) {
+        self.set_option_bool("b_coverage", enabled);
+    }
 
-        Ok(())
+    /// Sets whether the generated pkg-config files are relocatable, using the
+    /// `pkgconfig.relocatable` option.
+    ///
+    /// The paths in relocatable files are relative to `${prefix}`, for prebuilt trees moved
+    /// after installation.
+    pub fn set_pkgconfig_relocatable(&mut self, enabled: bool) {
+        self.set_option_bool("pkgconfig.relocatable", enabled);
     }
 
-    /// Returns the version of Meson installed on this system.
-    fn get_version_of_meson(meson_path: impl AsRef<Path>) -> Result<Version> {
-        let mut command = Command::new(meson_path.as_ref());
+    /// Sets the optimization level using the `optimization` option, overriding the buildtype
+    /// default.
+    ///
+    /// One of `plain`, `0`, `g`, `1`, `2`, `3` or `s`.
+    pub fn set_optimization(&mut self, level: &str) {
+        self.set_option("optimization", level);
+    }
 
-        command.arg("--version");
+    /// Sets whether debug info is generated using the `debug` option, overriding the buildtype
+    /// default.
+    pub fn set_debug(&mut self, enabled: bool) {
+        self.set_option_bool("debug", enabled);
+    }
 
-        let output = command.output()?;
+    /// Sets the kind of libraries built by the project using the `default_library` option.
+    ///
+    /// [`Config::emit_cargo_metadata`] links the libraries it finds: with
+    /// [`LibraryKind::Both`] the shared ones are preferred unless `static_only` is set.
+    pub fn set_default_library(&mut self, kind: LibraryKind) {
+        self.set_option("default_library", kind.as_str());
+    }
 
-        if !output.status.success() {
-            match output.status.code() {
-                Some(code) => return Err(Error::MesonExitedUnsuccessfully(code)),
-                None => return Err(Error::MesonExitedBySignal),
-            }
+    /// Sets the compiler warning level from `0` to `3` using the `warning_level` option.
+    ///
+    /// Level `4` enables all the warnings, like the `everything` level of meson. Higher levels
+    /// fail with [`Error::InvalidWarningLevel`].
+    pub fn set_warning_level(&mut self, level: u8) -> Result<()> {
+        match level {
+            0..=3 => self.set_option("warning_level", &level.to_string()),
+            4 => self.set_option("warning_level", "everything"),
+            _ => return Err(Error::InvalidWarningLevel(level)),
         }
+        Ok(())
+    }
 
-        let version_raw = core::str::from_utf8(&output.stdout)?.trim();
-        let version = Version::parse(version_raw)?;
+    /// Sets whether compiler warnings are treated as errors using the `werror` option.
+    pub fn set_werror(&mut self, enabled: bool) {
+        self.set_option_bool("werror", enabled);
+    }
 
-        Ok(version)
+    /// Sets whether the sources are compiled as unity builds using the `unity` option.
+    ///
+    /// Unity builds compile faster, but may break sources relying on file-local definitions.
+    pub fn set_unity(&mut self, mode: UnityMode) {
+        self.set_option("unity", mode.as_str());
     }
 
-    fn find_meson_target_specific_env() -> Option<PathBuf> {
-        let target = env::var("TARGET").ok()?;
-        let target_upper_case = target.to_uppercase().replace("-", "_");
-        let target_specific_env = format!("MESON_{target_upper_case}");
-        env::var_os(target_specific_env.as_str()).map(|x| x.into())
+    /// Sets the number of sources combined in each unity file using the `unity_size` option.
+    pub fn set_unity_size(&mut self, size: usize) {
+        self.set_option("unity_size", &size.to_string());
     }
 
-    fn find_meson_in_system() -> Result<PathBuf> {
-        if let Some(meson) = Self::find_meson_target_specific_env() {
-            return Ok(meson);
-        }
+    /// Sets the meson array build option, e.g. `c_args`
+    ///
+    /// Values are quoted, so they can contain commas and quotes.
+    pub fn set_option_array(&mut self, key: &str, values: &[&str]) {
+        self.set_option(key, &options::format_array(values));
+    }
 
-        if let Some(meson) = env::var_os("MESON") {
-            return Ok(meson.into());
-        }
+    /// Adds an argument to the `c_args` option, e.g. a define or an include path.
+    ///
+    /// Overrides `c_args` set by [`Config::set_option`].
+    pub fn add_c_arg(&mut self, arg: &str) {
+        self.c_args.push(arg.to_string());
+    }
 
-        return Ok("meson".into());
+    /// Adds an argument to the `cpp_args` option.
+    ///
+    /// Overrides `cpp_args` set by [`Config::set_option`].
+    pub fn add_cpp_arg(&mut self, arg: &str) {
+        self.cpp_args.push(arg.to_string());
     }
 
-    fn out_path(&self) -> PathBuf {
-        if let Some(path) = &self.out_path {
-            path.to_owned()
-        } else {
-            let out_path = env::var_os("OUT_DIR")
-                .expect("OUT_DIR is not set. Are you running outside of build.rs?");
-            out_path.into()
-        }
+    /// Adds an argument to both `c_link_args` and `cpp_link_args` options.
+    ///
+    /// Overrides these options set by [`Config::set_option`].
+    pub fn add_link_arg(&mut self, arg: &str) {
+        self.link_args.push(arg.to_string());
     }
 
-    fn profile(&self) -> &str {
-        match self.profile {
-            Some(ref profile) => &profile,
-            None => match env::var("PROFILE").unwrap().as_str() {
-                "debug" => "debug",
-                "release" => "release",
-                profile => {
-                    println!(
-                        "cargo:warning=PROFILE '{profile}' is unknown.
-                        Using release as default. Please override profile using set_profile"
-                    );
-                    "release"
-                }
-            },
-        }
+    /// Adds a path to the `cmake_prefix_path` option, where the CMake dependencies of the
+    /// project are searched for.
+    ///
+    /// Overrides the option set by [`Config::set_option`].
+    pub fn add_cmake_prefix_path(&mut self, path: &Path) {
+        self.cmake_prefix_paths
+            .push(path.to_string_lossy().into_owned());
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// Set the meson build profile passed by `--buildtype` argument
+    ///
+    /// Must be one of meson's buildtypes (`plain`, `debug`, `debugoptimized`, `release`,
+    /// `minsize`, `custom`), otherwise configuring and [`Config::sync_options`] fail with
+    /// [`Error::InvalidBuildtype`].
+    pub fn set_profile(&mut self, profile: &str) {
+        self.profile = Some(profile.to_string());
+    }
+
+    /// Sets the meson build profile matching `OPT_LEVEL` and `DEBUG` provided by Cargo.
+    ///
+    /// For example, `opt-level = 0` with debug info maps to `debug`, `opt-level = 3` without
+    /// it maps to `release`. Does nothing if the variables are not set.
+    pub fn set_buildtype_from_cargo(&mut self) {
+        let (Some(opt_level), Some(debug)) = (self.var("OPT_LEVEL"), self.var("DEBUG")) else {
+            return;
+        };
+
+        let debug = !matches!(debug.as_str(), "false" | "0" | "none");
+        self.set_profile(buildtype_from_cargo(&opt_level, debug));
+    }
+
+    /// Sets the backend meson generates build files for.
+    ///
+    /// If not set, meson picks its default backend, which is `ninja`.
+    pub fn set_backend(&mut self, backend: Backend) {
+        self.backend = Some(backend);
+    }
+
+    /// Sets how meson handles subproject wraps.
+    ///
+    /// Use [`WrapMode::NoDownload`] for offline builds. If not set, meson uses its default.
+    pub fn set_wrap_mode(&mut self, mode: WrapMode) {
+        self.wrap_mode = Some(mode);
+    }
+
+    /// Sets whether meson activates the Visual Studio environment with `--vsenv`.
+    ///
+    /// Needed to find MSVC on Windows outside of a Developer Prompt, ignored on other platforms.
+    pub fn set_vsenv(&mut self, enabled: bool) {
+        self.vsenv = enabled;
+    }
+
+    /// Sets the time limit for each spawned meson command.
+    ///
+    /// A command running longer is killed and [`Error::MesonTimedOut`] is returned.
+    /// There is no limit by default.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = Some(timeout);
+    }
+
+    /// Sets the number of parallel jobs for the compile step.
+    ///
+    /// `0` lets meson decide. If not set, `NUM_JOBS` provided by Cargo is used if present.
+    pub fn set_jobs(&mut self, jobs: usize) {
+        self.jobs = Some(jobs);
+    }
+
+    /// Sets whether the compile step prints the full command lines run by the backend,
+    /// disabled by default.
+    ///
+    /// `meson install` has no verbose mode, so only the compile step is affected.
+    pub fn set_verbose(&mut self, enabled: bool) {
+        self.verbose = enabled;
+    }
+
+    /// Sets whether the informational `cargo:info=` lines are suppressed, disabled by default.
+    ///
+    /// `cargo:warning=` lines are still printed.
+    pub fn set_quiet(&mut self, enabled: bool) {
+        self.quiet = enabled;
+    }
+
+    /// Sets the targets built by the compile step, the whole project is built if empty.
+    ///
+    /// Targets are named as in `meson compile`, e.g. `foo` or `path/to/foo:shared_library`.
+    pub fn set_targets(&mut self, targets: &[&str]) {
+        self.targets = targets.iter().map(|target| target.to_string()).collect();
+    }
+
+    /// Limits the subprojects processed by the build to the `include` list.
+    ///
+    /// Meson configures and compiles all subprojects, so the filter only skips installing the
+    /// others using `--skip-subprojects` of `meson install`. Combine it with
+    /// [`Config::set_targets`] to compile only the needed targets.
+    pub fn set_subproject_filter(&mut self, include: &[&str]) {
+        let include = include.iter().map(|name| name.to_string()).collect();
+        self.subproject_filter = Some(include);
+    }
+
+    /// Sets whether the setup, compile, install and test commands are only printed to stdout
+    /// instead of running them, disabled by default.
+    pub fn set_dry_run(&mut self, enabled: bool) {
+        self.dry_run = enabled;
+    }
+
+    /// Sets a callback receiving the output of meson commands line by line instead of stdout,
+    /// e.g. to forward it into a logger.
+    ///
+    /// Lines of stdout and stderr are passed as they arrive, the callback may be called from
+    /// several threads.
+    pub fn set_output_callback(&mut self, callback: impl Fn(&str) + Send + Sync + 'static) {
+        self.output_callback = Some(OutputCallback(Arc::new(callback)));
+    }
+
+    /// Sets a callback receiving the `[done/total]` progress of ninja, e.g. for build UIs.
+    ///
+    /// The progress is parsed from each stdout line as it arrives. Lines without progress are
+    /// ignored.
+    pub fn set_progress_callback(
+        &mut self,
+        callback: impl Fn(usize, usize) + Send + Sync + 'static,
+    ) {
+        self.progress_callback = Some(ProgressCallback(Arc::new(callback)));
+    }
+
+    /// Adds a raw argument to `meson setup`, e.g. `--vsenv`.
+    ///
+    /// Extra arguments are passed in the order they were added, after all arguments
+    /// generated from the config and right before the build and source directories. So they
+    /// can override generated ones.
+    ///
+    /// Arguments from the `MESON_ARGS_<TARGET_UPPER_CASE>` or `MESON_ARGS` environment variable
+    /// follow them, split into words like a POSIX shell: words are separated by whitespace and
+    /// can be quoted with `'` or `"`, or escaped with `\`.
+    pub fn add_setup_arg(&mut self, arg: impl Into<OsString>) {
+        self.extra_setup_args.push(arg.into());
+    }
+
+    /// Adds raw arguments to `meson setup`: see [`Config::add_setup_arg`].
+    pub fn add_setup_args(&mut self, args: &[&str]) {
+        self.extra_setup_args
+            .extend(args.iter().map(OsString::from));
+    }
+
+    /// Forces `meson setup --reconfigure` on an already configured build directory.
+    ///
+    /// Normally, setup is skipped if the build directory is configured, so options changed
+    /// afterwards have no effect. Note that when enabled, setup runs on every build.
+    pub fn set_reconfigure(&mut self, enabled: bool) {
+        self.reconfigure = enabled;
+    }
+
+    /// Passes `--wipe` to `meson setup` of an already configured build directory.
+    ///
+    /// Meson regenerates the build directory from scratch, keeping the command line options.
+    /// As with [`Config::set_reconfigure`], setup runs on every build when enabled.
+    pub fn set_wipe(&mut self, enabled: bool) {
+        self.wipe = enabled;
+    }
+
+    /// Builder-style variant of [`Config::set_native_file`].
+    pub fn with_native_file(mut self, file: &Path) -> Self {
+        self.set_native_file(file);
+        self
+    }
+
+    /// Builder-style variant of [`Config::add_native_file`].
+    pub fn with_added_native_file(mut self, file: &Path) -> Self {
+        self.add_native_file(file);
+        self
+    }
+
+    /// Builder-style variant of [`Config::set_cross_file`].
+    pub fn with_cross_file(mut self, file: &Path) -> Self {
+        self.set_cross_file(file);
+        self
+    }
+
+    /// Builder-style variant of [`Config::add_cross_file`].
+    pub fn with_added_cross_file(mut self, file: &Path) -> Self {
+        self.add_cross_file(file);
+        self
+    }
+
+    /// Builder-style variant of [`Config::set_options_file`].
+    pub fn with_options_file(mut self, file: &Path) -> Self {
+        self.set_options_file(file);
+        self
+    }
+
+    /// Builder-style variant of [`Config::set_out_path`].
+    pub fn with_out_path(mut self, path: &Path) -> Self {
+        self.set_out_path(path);
+        self
+    }
+
+    /// Builder-style variant of [`Config::set_build_dir_name`].
+    pub fn with_build_dir_name(mut self, name: &str) -> Self {
+        self.set_build_dir_name(name);
+        self
+    }
+
+    /// Builder-style variant of [`Config::set_external_build_dir`].
+    pub fn with_external_build_dir(mut self, dir: &Path) -> Self {
+        self.set_external_build_dir(dir);
+        self
+    }
+
+    /// Builder-style variant of [`Config::set_install_dir_name`].
+    pub fn with_install_dir_name(mut self, name: &str) -> Self {
+        self.set_install_dir_name(name);
+        self
+    }
+
+    /// Builder-style variant of [`Config::set_install`].
+    pub fn with_install(mut self, enabled: bool) -> Self {
+        self.set_install(enabled);
+        self
+    }
+
+    /// Builder-style variant of [`Config::set_destdir`].
+    pub fn with_destdir(mut self, dir: &Path) -> Self {
+        self.set_destdir(dir);
+        self
+    }
+
+    /// Builder-style variant of [`Config::set_prefix`].
+    pub fn with_prefix(mut self, prefix: &Path) -> Self {
+        self.set_prefix(prefix);
+        self
+    }
+
+    /// Builder-style variant of [`Config::set_strip`].
+    pub fn with_strip(mut self, enabled: bool) -> Self {
+        self.set_strip(enabled);
+        self
+    }
+
+    /// Builder-style variant of [`Config::set_install_tags`].
+    pub fn with_install_tags(mut self, tags: &[&str]) -> Self {
+        self.set_install_tags(tags);
+        self
+    }
+
+    /// Builder-style variant of [`Config::set_env`].
+    pub fn with_env(mut self, key: &str, value: &str) -> Self {
+        self.set_env(key, value);
+        self
+    }
+
+    /// Builder-style variant of [`Config::remove_env`].
+    pub fn without_env(mut self, key: &str) -> Self {
+        self.remove_env(key);
+        self
+    }
+
+    /// Builder-style variant of [`Config::set_compiler_cache`].
+    pub fn with_compiler_cache(mut self, cache: CompilerCache) -> Self {
+        self.set_compiler_cache(cache);
+        self
+    }
+
+    /// Builder-style variant of [`Config::set_subprojects_dir`].
+    pub fn with_subprojects_dir(mut self, dir: &Path) -> Self {
+        self.set_subprojects_dir(dir);
+        self
+    }
+
+    /// Builder-style variant of [`Config::set_ninja_path`].
+    pub fn with_ninja_path(mut self, path: &Path) -> Self {
+        self.set_ninja_path(path);
+        self
+    }
+
+    /// Builder-style variant of [`Config::set_libdir`].
+    pub fn with_libdir(mut self, dir: &Path) -> Self {
+        self.set_libdir(dir);
+        self
+    }
+
+    /// Builder-style variant of [`Config::set_bindir`].
+    pub fn with_bindir(mut self, dir: &Path) -> Self {
+        self.set_bindir(dir);
+        self
+    }
+
+    /// Builder-style variant of [`Config::set_includedir`].
+    pub fn with_includedir(mut self, dir: &Path) -> Self {
+        self.set_includedir(dir);
+        self
+    }
+
+    /// Builder-style variant of [`Config::set_datadir`].
+    pub fn with_datadir(mut self, dir: &Path) -> Self {
+        self.set_datadir(dir);
+        self
+    }
+
+    /// Builder-style variant of [`Config::set_option`].
+    pub fn with_option(mut self, key: &str, value: &str) -> Self {
+        self.set_option(key, value);
+        self
+    }
+
+    /// Builder-style variant of [`Config::set_option_default`].
+    pub fn with_option_default(mut self, key: &str, value: &str) -> Self {
+        self.set_option_default(key, value);
+        self
+    }
+
+    /// Builder-style variant of [`Config::set_option_bool`].
+    pub fn with_option_bool(mut self, key: &str, value: bool) -> Self {
+        self.set_option_bool(key, value);
+        self
+    }
+
+    /// Builder-style variant of [`Config::set_option_feature`].
+    pub fn with_option_feature(mut self, key: &str, value: Feature) -> Self {
+        self.set_option_feature(key, value);
+        self
+    }
+
+    /// Builder-style variant of [`Config::set_sanitizer`].
+    pub fn with_sanitizer(mut self, sanitizer: Sanitizer) -> Self {
+        self.set_sanitizer(sanitizer);
+        self
+    }
+
+    /// Builder-style variant of [`Config::set_lto`].
+    pub fn with_lto(mut self, enabled: bool) -> Self {
+        self.set_lto(enabled);
+        self
+    }
+
+    /// Builder-style variant of [`Config::set_lto_mode`].
+    pub fn with_lto_mode(mut self, mode: LtoMode) -> Self {
+        self.set_lto_mode(mode);
+        self
+    }
+
+    /// Builder-style variant of [`Config::set_coverage`].
+    pub fn with_coverage(mut self, enabled: bool) -> Self {
+        self.set_coverage(enabled);
+        self
+    }
+
+    /// Builder-style variant of [`Config::set_pkgconfig_relocatable`].
+    pub fn with_pkgconfig_relocatable(mut self, enabled: bool) -> Self {
+        self.set_pkgconfig_relocatable(enabled);
+        self
+    }
+
+    /// Builder-style variant of [`Config::set_optimization`].
+    pub fn with_optimization(mut self, level: &str) -> Self {
+        self.set_optimization(level);
+        self
+    }
+
+    /// Builder-style variant of [`Config::set_debug`].
+    pub fn with_debug(mut self, enabled: bool) -> Self {
+        self.set_debug(enabled);
+        self
+    }
+
+    /// Builder-style variant of [`Config::set_default_library`].
+    pub fn with_default_library(mut self, kind: LibraryKind) -> Self {
+        self.set_default_library(kind);
+        self
+    }
+
+    /// Builder-style variant of [`Config::set_warning_level`].
+    pub fn with_warning_level(mut self, level: u8) -> Result<Self> {
+        self.set_warning_level(level)?;
+        Ok(self)
+    }
+
+    /// Builder-style variant of [`Config::set_werror`].
+    pub fn with_werror(mut self, enabled: bool) -> Self {
+        self.set_werror(enabled);
+        self
+    }
+
+    /// Builder-style variant of [`Config::set_unity`].
+    pub fn with_unity(mut self, mode: UnityMode) -> Self {
+        self.set_unity(mode);
+        self
+    }
+
+    /// Builder-style variant of [`Config::set_unity_size`].
+    pub fn with_unity_size(mut self, size: usize) -> Self {
+        self.set_unity_size(size);
+        self
+    }
+
+    /// Builder-style variant of [`Config::set_option_array`].
+    pub fn with_option_array(mut self, key: &str, values: &[&str]) -> Self {
+        self.set_option_array(key, values);
+        self
+    }
+
+    /// Builder-style variant of [`Config::add_c_arg`].
+    pub fn with_c_arg(mut self, arg: &str) -> Self {
+        self.add_c_arg(arg);
+        self
+    }
+
+    /// Builder-style variant of [`Config::add_cpp_arg`].
+    pub fn with_cpp_arg(mut self, arg: &str) -> Self {
+        self.add_cpp_arg(arg);
+        self
+    }
+
+    /// Builder-style variant of [`Config::add_link_arg`].
+    pub fn with_link_arg(mut self, arg: &str) -> Self {
+        self.add_link_arg(arg);
+        self
+    }
+
+    /// Builder-style variant of [`Config::add_cmake_prefix_path`].
+    pub fn with_cmake_prefix_path(mut self, path: &Path) -> Self {
+        self.add_cmake_prefix_path(path);
+        self
+    }
+
+    /// Builder-style variant of [`Config::set_profile`].
+    pub fn with_profile(mut self, profile: &str) -> Self {
+        self.set_profile(profile);
+        self
+    }
+
+    /// Builder-style variant of [`Config::set_buildtype_from_cargo`].
+    pub fn with_buildtype_from_cargo(mut self) -> Self {
+        self.set_buildtype_from_cargo();
+        self
+    }
+
+    /// Builder-style variant of [`Config::set_backend`].
+    pub fn with_backend(mut self, backend: Backend) -> Self {
+        self.set_backend(backend);
+        self
+    }
+
+    /// Builder-style variant of [`Config::set_wrap_mode`].
+    pub fn with_wrap_mode(mut self, mode: WrapMode) -> Self {
+        self.set_wrap_mode(mode);
+        self
+    }
+
+    /// Builder-style variant of [`Config::set_vsenv`].
+    pub fn with_vsenv(mut self, enabled: bool) -> Self {
+        self.set_vsenv(enabled);
+        self
+    }
+
+    /// Builder-style variant of [`Config::set_timeout`].
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.set_timeout(timeout);
+        self
+    }
+
+    /// Builder-style variant of [`Config::set_jobs`].
+    pub fn with_jobs(mut self, jobs: usize) -> Self {
+        self.set_jobs(jobs);
+        self
+    }
+
+    /// Builder-style variant of [`Config::set_verbose`].
+    pub fn with_verbose(mut self, enabled: bool) -> Self {
+        self.set_verbose(enabled);
+        self
+    }
+
+    /// Builder-style variant of [`Config::set_quiet`].
+    pub fn with_quiet(mut self, enabled: bool) -> Self {
+        self.set_quiet(enabled);
+        self
+    }
+
+    /// Builder-style variant of [`Config::set_targets`].
+    pub fn with_targets(mut self, targets: &[&str]) -> Self {
+        self.set_targets(targets);
+        self
+    }
+
+    /// Builder-style variant of [`Config::set_subproject_filter`].
+    pub fn with_subproject_filter(mut self, include: &[&str]) -> Self {
+        self.set_subproject_filter(include);
+        self
+    }
+
+    /// Builder-style variant of [`Config::set_dry_run`].
+    pub fn with_dry_run(mut self, enabled: bool) -> Self {
+        self.set_dry_run(enabled);
+        self
+    }
+
+    /// Builder-style variant of [`Config::set_output_callback`].
+    pub fn with_output_callback(mut self, callback: impl Fn(&str) + Send + Sync + 'static) -> Self {
+        self.set_output_callback(callback);
+        self
+    }
+
+    /// Builder-style variant of [`Config::set_progress_callback`].
+    pub fn with_progress_callback(
+        mut self,
+        callback: impl Fn(usize, usize) + Send + Sync + 'static,
+    ) -> Self {
+        self.set_progress_callback(callback);
+        self
+    }
+
+    /// Builder-style variant of [`Config::add_setup_arg`].
+    pub fn with_setup_arg(mut self, arg: impl Into<OsString>) -> Self {
+        self.add_setup_arg(arg);
+        self
+    }
+
+    /// Builder-style variant of [`Config::add_setup_args`].
+    pub fn with_setup_args(mut self, args: &[&str]) -> Self {
+        self.add_setup_args(args);
+        self
+    }
+
+    /// Builder-style variant of [`Config::set_reconfigure`].
+    pub fn with_reconfigure(mut self, enabled: bool) -> Self {
+        self.set_reconfigure(enabled);
+        self
+    }
+
+    /// Builder-style variant of [`Config::set_wipe`].
+    pub fn with_wipe(mut self, enabled: bool) -> Self {
+        self.set_wipe(enabled);
+        self
+    }
+
+    /// Get the path of the output directory.
+    ///
+    /// It is the path set by [`Config::set_out_path`] or `OUT_DIR` provided by Cargo
+    /// to build scripts. Fails with [`Error::OutDirNotSet`] if neither is available.
+    pub fn out_path(&self) -> Result<PathBuf> {
+        if let Some(path) = &self.out_path {
+            Ok(path.to_owned())
+        } else {
+            let out_path = self.var_os("OUT_DIR").ok_or(Error::OutDirNotSet)?;
+            Ok(out_path.into())
+        }
+    }
+
+    /// Get the path of the build directory.
+    pub fn build_dir(&self) -> Result<PathBuf> {
+        if let Some(ref dir) = self.external_build_dir {
+            return Ok(dir.clone());
+        }
+        Ok(self.out_path()?.join(&self.build_dir_name))
+    }
+
+    /// Whether [`Config::build_dir`] contains a build tree generated by `meson setup`.
+    ///
+    /// Checks the file generated for the backend: `build.ninja`, a `.sln` solution for
+    /// Visual Studio or an `.xcodeproj` project for Xcode.
+    pub fn is_configured(&self) -> bool {
+        let Ok(build_dir) = self.build_dir() else {
+            return false;
+        };
+
+        match self.backend.unwrap_or(Backend::Ninja) {
+            Backend::Ninja => build_dir.join("build.ninja").exists(),
+            Backend::Vs | Backend::Vs2017 | Backend::Vs2019 | Backend::Vs2022 => {
+                contains_extension(&build_dir, "sln")
+            }
+            Backend::Xcode => contains_extension(&build_dir, "xcodeproj"),
+            Backend::None => build_dir
+                .join("meson-private")
+                .join("coredata.dat")
+                .exists(),
+        }
+    }
+
+    /// Get the path of the install directory.
+    pub fn install_dir(&self) -> Result<PathBuf> {
+        Ok(self.out_path()?.join(&self.install_dir_name))
+    }
+
+    /// Prints `cargo:rustc-link-search` and `cargo:rustc-link-lib` lines for the libraries
+    /// installed into `lib` and `lib64` of [`Config::install_dir`], or into the directory set by
+    /// [`Config::set_libdir`].
+    ///
+    /// The link name is derived from the file name, e.g. `libfoo.a` is linked as `static=foo`
+    /// and `libfoo.so` as `dylib=foo`. If `static_only` is set, shared libraries are ignored.
+    ///
+    /// Should be called after [`Config::build`].
+    pub fn emit_cargo_metadata(&self, static_only: bool) -> Result<()> {
+        for line in self.cargo_metadata(static_only)? {
+            self.print(&line);
+        }
+
+        Ok(())
+    }
+
+    fn cargo_metadata(&self, static_only: bool) -> Result<Vec<String>> {
+        cargo::link_directives(&self.lib_dirs()?, static_only)
+    }
+
+    /// Prints a `cargo:rustc-env` line adding the installed shared libraries to the search path
+    /// of the dynamic linker, for tests loading them at runtime.
+    ///
+    /// The variable depends on the target OS: `LD_LIBRARY_PATH`, `DYLD_LIBRARY_PATH` on macOS
+    /// and `PATH` with the `bin` directory on Windows. It is only set at compile time, read it
+    /// with `env!` to pass it to the spawned processes.
+    ///
+    /// Should be called after [`Config::build`].
+    pub fn emit_runtime_library_path(&self) -> Result<()> {
+        for line in self.runtime_library_path()? {
+            self.print(&line);
+        }
+
+        Ok(())
+    }
+
+    fn runtime_library_path(&self) -> Result<Vec<String>> {
+        let target_os = self
+            .var("CARGO_CFG_TARGET_OS")
+            .unwrap_or_else(|| env::consts::OS.to_string());
+        let var = cargo::runtime_library_path_var(&target_os);
+
+        let dirs = if target_os == "windows" {
+            let bindir = self.bindir.as_deref().unwrap_or(Path::new("bin"));
+            vec![self.install_dir()?.join(bindir)]
+        } else {
+            self.lib_dirs()?
+        };
+
+        cargo::runtime_library_path(var, &dirs, self.var_os(var))
+    }
+
+    /// Directories in the install tree where libraries are searched for.
+    fn lib_dirs(&self) -> Result<Vec<PathBuf>> {
+        let install_dir = self.install_dir()?;
+        let lib_dirs = match self.libdir {
+            Some(ref libdir) => vec![install_dir.join(libdir)],
+            None => vec![install_dir.join("lib"), install_dir.join("lib64")],
+        };
+
+        Ok(lib_dirs)
+    }
+
+    /// Prints `cargo:rerun-if-changed` lines for `meson.build` and meson options files
+    /// in `source_dir`, and `cargo:rerun-if-env-changed` lines for the variables used to find meson.
+    pub fn emit_rerun_if_changed(&self, source_dir: &Path) -> Result<()> {
+        for line in self.rerun_if_changed(source_dir, false)? {
+            self.print(&line);
+        }
+
+        Ok(())
+    }
+
+    /// Same as [`Config::emit_rerun_if_changed`], but includes every file in `source_dir`.
+    pub fn emit_rerun_if_changed_with_sources(&self, source_dir: &Path) -> Result<()> {
+        for line in self.rerun_if_changed(source_dir, true)? {
+            self.print(&line);
+        }
+
+        Ok(())
+    }
+
+    fn rerun_if_changed(&self, source_dir: &Path, include_sources: bool) -> Result<Vec<String>> {
+        let mut lines = cargo::rerun_if_changed(source_dir, include_sources)?;
+
+        for var in ["MESON", "MESON_ARGS"] {
+            lines.push(format!("cargo:rerun-if-env-changed={var}"));
+            if let Some(target_specific_env) = target_specific_env_name(var, |var| self.var(var)) {
+                lines.push(format!("cargo:rerun-if-env-changed={target_specific_env}"));
+            }
+        }
+
+        Ok(lines)
+    }
+
+    /// Probes `package` using pkg-config with the `.pc` files installed by meson
+    /// and prints the resulting link flags to Cargo.
+    ///
+    /// The `pkgconfig` folders of the library directories searched by
+    /// [`Config::emit_cargo_metadata`] are prepended to `PKG_CONFIG_PATH` of the current process.
+    ///
+    /// Should be called after [`Config::build`].
+    pub fn emit_pkg_config(&self, package: &str) -> Result<pkg_config::Library> {
+        let pkg_config_dirs: Vec<PathBuf> = self
+            .lib_dirs()?
+            .iter()
+            .map(|lib_dir| lib_dir.join("pkgconfig"))
+            .collect();
+
+        let pc_file = format!("{package}.pc");
+        if !pkg_config_dirs
+            .iter()
+            .any(|dir| dir.join(&pc_file).exists())
+        {
+            return Err(Error::PkgConfigFileNotFound(package.to_string()));
+        }
+
+        let mut search_path = pkg_config_dirs;
+        if let Some(existing) = self.var_os("PKG_CONFIG_PATH") {
+            search_path.extend(env::split_paths(&existing));
+        }
+        let search_path = env::join_paths(search_path)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+        env::set_var("PKG_CONFIG_PATH", search_path);
+
+        Ok(pkg_config::Config::new().probe(package)?)
+    }
+
+    /// Path of the `compile_commands.json` compilation database generated by the ninja backend.
+    ///
+    /// Returns `None` if the project is not configured or the file does not exist.
+    pub fn compile_commands_path(&self) -> Option<PathBuf> {
+        let path = self.build_dir().ok()?.join("compile_commands.json");
+        path.exists().then_some(path)
+    }
+
+    /// Prints the path of `compile_commands.json` as a `cargo:compile_commands=` metadata line,
+    /// available to dependent build scripts as `DEP_<links>_COMPILE_COMMANDS`.
+    pub fn emit_compile_commands_path(&self) {
+        if let Some(path) = self.compile_commands_path() {
+            self.print(&format!("cargo:compile_commands={}", path.display()));
+        }
+    }
+
+    /// Prints the installed headers directory as a `cargo:include=` metadata line, available to
+    /// dependent build scripts as `DEP_<links>_INCLUDE` following the `-sys` crate convention.
+    ///
+    /// The directory is `include` of [`Config::install_dir`], or the one set by
+    /// [`Config::set_includedir`].
+    pub fn emit_include_metadata(&self) -> Result<()> {
+        self.print(&self.include_metadata()?);
+        Ok(())
+    }
+
+    fn include_metadata(&self) -> Result<String> {
+        let includedir = self.includedir.as_deref().unwrap_or(Path::new("include"));
+        let include_dir = absolute(&self.install_dir()?.join(includedir))?;
+        Ok(format!("cargo:include={}", include_dir.display()))
+    }
+
+    /// Removes the build directory entirely.
+    pub fn clean(&self) -> Result<()> {
+        match std::fs::remove_dir_all(self.build_dir()?) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// The options passed to meson including the accumulated compiler arguments.
+    fn effective_options(&self) -> BTreeMap<String, String> {
+        let mut options = self.options.clone();
+
+        let array_options = [
+            ("c_args", &self.c_args),
+            ("cpp_args", &self.cpp_args),
+            ("c_link_args", &self.link_args),
+            ("cpp_link_args", &self.link_args),
+            ("cmake_prefix_path", &self.cmake_prefix_paths),
+        ];
+
+        for (key, values) in array_options {
+            if !values.is_empty() {
+                options.insert(key.to_string(), options::format_array(values));
+            }
+        }
+
+        options
+    }
+
+    /// Path of the file recording the configuration used by the last `meson setup`.
+    fn configuration_state_path(&self) -> Result<PathBuf> {
+        Ok(self.build_dir()?.join("meson-ext-rs.json"))
+    }
+
+    /// Path of the file recording the meson version used by the last `meson setup`.
+    fn meson_version_marker_path(&self) -> Result<PathBuf> {
+        Ok(self.build_dir()?.join("meson-ext-rs.version"))
+    }
+
+    /// Whether the build directory was configured by another meson version.
+    ///
+    /// Build directories without a marker are assumed to match.
+    fn meson_version_changed(&self) -> bool {
+        let Ok(marker_path) = self.meson_version_marker_path() else {
+            return false;
+        };
+
+        match std::fs::read_to_string(marker_path) {
+            Ok(version) => version.trim() != self.meson_version.to_string(),
+            Err(_) => false,
+        }
+    }
+
+    /// JSON describing the configuration passed to `meson setup`.
+    ///
+    /// Compared as a whole against the recorded one to detect changes. The first line holds
+    /// the options and buildtype, which `meson configure` can change on its own.
+    fn configuration_state(&self) -> String {
+        format!("{}\n{}\n", self.options_state(), self.setup_state())
+    }
+
+    /// The part of [`Config::configuration_state`] applied by [`Config::sync_options`].
+    fn options_state(&self) -> String {
+        let options: Vec<String> = self
+            .effective_options()
+            .iter()
+            .map(|(key, value)| format!("{}:{}", json_string(key), json_string(value)))
+            .collect();
+
+        format!(
+            "{{\"options\":{{{}}},\"profile\":{}}}",
+            options.join(","),
+            json_string(self.profile()),
+        )
+    }
+
+    /// The part of [`Config::configuration_state`] only applied by `meson setup`.
+    fn setup_state(&self) -> String {
+        let path = |path: &Option<PathBuf>| match path {
+            Some(path) => json_string(&path.to_string_lossy()),
+            None => "null".to_string(),
+        };
+
+        let paths = |paths: &[PathBuf]| {
+            let paths: Vec<String> = paths
+                .iter()
+                .map(|path| json_string(&path.to_string_lossy()))
+                .collect();
+            paths.join(",")
+        };
+
+        let extra_args: Vec<String> = self
+            .extra_setup_args
+            .iter()
+            .map(|arg| json_string(&arg.to_string_lossy()))
+            .collect();
+
+        // The mirror of the source tree is only updated on setup
+        let options_file = match self.options_file {
+            Some(ref file) => {
+                let mut hasher = DefaultHasher::new();
+                std::fs::read(file).ok().hash(&mut hasher);
+                format!(
+                    "{{\"path\":{},\"hash\":\"{:016x}\"}}",
+                    json_string(&file.to_string_lossy()),
+                    hasher.finish()
+                )
+            }
+            None => "null".to_string(),
+        };
+
+        format!(
+            "{{\"native_files\":[{}],\"cross_files\":[{}],\"prefix\":{},\"extra_args\":[{}],\
+            \"options_file\":{}}}",
+            paths(&self.native_files),
+            paths(&self.cross_files),
+            path(&self.prefix),
+            extra_args.join(","),
+            options_file,
+        )
+    }
+
+    /// Whether the configuration differs from the one used by the last `meson setup`.
+    fn configuration_changed(&self) -> bool {
+        let Ok(state_path) = self.configuration_state_path() else {
+            return true;
+        };
+
+        match std::fs::read_to_string(state_path) {
+            Ok(state) => state != self.configuration_state(),
+            Err(_) => true,
+        }
+    }
+
+    /// Runs `meson setup` if needed, returns whether it ran.
+    ///
+    /// Holds the [`Config::configure_lock_path`] lock, so processes sharing the build directory
+    /// configure it one at a time.
+    fn configure(&self, source_dir: &Path) -> Result<bool> {
+        let _lock = match self.configure_lock_path()? {
+            Some(lock_path) => Some(FileLock::acquire(&lock_path)?),
+            None => None,
+        };
+        let Some(mut command) = self.configure_command(source_dir)? else {
+            return Ok(false);
+        };
+
+        self.run(&mut command, |code, stderr| {
+            self.configure_failed(code, stderr)
+        })?;
+
+        self.finish_configure()?;
+        Ok(true)
+    }
+
+    /// Records the configuration after the command of [`Config::configure_command`] succeeded,
+    /// nothing in dry-run mode.
+    fn finish_configure(&self) -> Result<()> {
+        if self.dry_run {
+            return Ok(());
+        }
+        self.save_configuration_state()
+    }
+
+    /// The directory meson is configured from: `source_dir`, or its mirror with the options
+    /// file set by [`Config::set_options_file`].
+    ///
+    /// The mirror is updated unless in dry-run mode.
+    fn configured_source_dir(&self, source_dir: &Path) -> Result<PathBuf> {
+        let Some(ref file) = self.options_file else {
+            return Ok(source_dir.to_owned());
+        };
+
+        let mirror = options_file::mirror_path(&self.out_path()?);
+        if !self.dry_run {
+            options_file::mirror_source_tree(source_dir, &mirror, file)?;
+        }
+        Ok(mirror)
+    }
+
+    /// Path of the lock file taken while configuring, next to the build directory.
+    ///
+    /// `None` if no lock is taken: with an external build directory, which is not configured,
+    /// or in dry-run mode, which leaves the filesystem untouched.
+    fn configure_lock_path(&self) -> Result<Option<PathBuf>> {
+        if self.external_build_dir.is_some() || self.dry_run {
+            return Ok(None);
+        }
+
+        let build_dir = self.build_dir()?;
+        if let Some(parent) = build_dir.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let mut path = build_dir.into_os_string();
+        path.push(".lock");
+        Ok(Some(path.into()))
+    }
+
+    /// The `meson setup` command to run before compiling, `None` if already configured.
+    fn configure_command(&self, source_dir: &Path) -> Result<Option<Command>> {
+        if !source_dir.join("meson.build").is_file() {
+            return Err(Error::NoMesonBuildFile(source_dir.to_owned()));
+        }
+
+        if self.external_build_dir.is_some() {
+            if !self.is_configured() {
+                return Err(Error::NotConfigured(self.build_dir()?));
+            }
+            return Ok(None);
+        }
+
+        if self.is_configured()
+            && !self.reconfigure
+            && !self.wipe
+            && !self.configuration_changed()
+            && !self.meson_version_changed()
+        {
+            return Ok(None);
+        }
+
+        if !self.dry_run {
+            std::fs::create_dir_all(self.build_dir()?)?;
+        }
+
+        let source_dir = self.configured_source_dir(source_dir)?;
+        self.setup_command(&source_dir).map(Some)
+    }
+
+    /// Records the configuration used by a successful `meson setup`.
+    fn save_configuration_state(&self) -> Result<()> {
+        std::fs::write(self.configuration_state_path()?, self.configuration_state())?;
+        std::fs::write(
+            self.meson_version_marker_path()?,
+            format!("{}\n", self.meson_version),
+        )?;
+        Ok(())
+    }
+
+    /// Records the options applied by `meson configure`, keeping the rest of the recorded
+    /// configuration.
+    ///
+    /// Nothing is recorded without a previous state, so the next configure runs `meson setup`.
+    fn save_options_state(&self) -> Result<()> {
+        let state_path = self.configuration_state_path()?;
+        let Ok(state) = std::fs::read_to_string(&state_path) else {
+            return Ok(());
+        };
+        let Some(setup_state) = state.lines().nth(1) else {
+            return Ok(());
+        };
+
+        let state = format!("{}\n{setup_state}\n", self.options_state());
+        std::fs::write(state_path, state)?;
+        Ok(())
+    }
+
+    /// The error of a failed `meson setup` or `meson configure`, pointing to the meson log.
+    fn configure_failed(&self, code: i32, stderr: String) -> Error {
+        let log_path = self
+            .build_dir()
+            .map(|build_dir| build_dir.join("meson-logs").join("meson-log.txt"))
+            .ok()
+            .filter(|log_path| log_path.is_file());
+
+        Error::MesonConfiguredUnsuccessfully {
+            code,
+            stderr,
+            log_path,
+        }
+    }
+
+    /// Runs a meson command, or only prints it in dry-run mode.
+    fn run(&self, command: &mut Command, failed: impl FnOnce(i32, String) -> Error) -> Result<()> {
+        if self.dry_run {
+            self.print(&format!("Dry run: {}", command::command_line(command)));
+            return Ok(());
+        }
+
+        command::run(
+            command,
+            self.timeout,
+            self.output_callback.as_ref(),
+            self.progress_callback.as_ref(),
+            failed,
+        )?;
+        Ok(())
+    }
+
+    /// A meson command with the environment overrides applied.
+    fn meson_command(&self) -> Command {
+        let mut command = Command::new(self.meson_path.clone());
+        command.args(&self.meson_args);
+        if let Some(ref ninja) = self.ninja_path {
+            command.env("NINJA", ninja);
+        }
+        if let Some(ref dir) = self.package_cache_dir {
+            command.env("MESON_PACKAGE_CACHE_DIR", dir);
+        }
+        for (key, value) in &self.env {
+            match value {
+                Some(value) => command.env(key, value),
+                None => command.env_remove(key),
+            };
+        }
+        command
+    }
+
+    /// The `meson setup` command for the project in `source_dir`.
+    fn setup_command(&self, source_dir: &Path) -> Result<Command> {
+        let mut command = self.meson_command();
+        command.current_dir(source_dir);
+        command.args(self.setup_args(source_dir)?);
+
+        if self.compiler_cache != CompilerCache::None {
+            for (var, default) in [("CC", "cc"), ("CXX", "c++")] {
+                let compiler = match self.env.get(var) {
+                    Some(value) => value.clone(),
+                    None => self.var(var),
+                };
+                let compiler = compiler.unwrap_or_else(|| default.to_string());
+                command.env(var, self.compiler_cache.wrap(&compiler));
+            }
+        }
+
+        Ok(command)
+    }
+
+    /// Arguments passed to `meson setup` for the project in `source_dir`, starting with `setup`.
+    ///
+    /// Useful to check the configuration without running meson.
+    pub fn setup_args(&self, source_dir: &Path) -> Result<Vec<OsString>> {
+        let mut args: Vec<String> = vec!["setup".to_string()];
+
+        // Meson refuses to reconfigure or wipe a directory without a build tree.
+        // Build trees of other meson versions are wiped, as they may be incompatible.
+        if self.is_configured() {
+            if self.wipe || self.meson_version_changed() {
+                args.push("--wipe".to_string());
+            } else if self.reconfigure || self.configuration_changed() {
+                args.push("--reconfigure".to_string());
+            }
+        }
+
+        self.report_profile();
+        let profile = self.validated_profile()?;
+        if !profile.is_empty() {
+            args.extend(["--buildtype".to_string(), profile.to_string()]);
+        } else {
+            self.info("profile is empty, ignoring profile option.");
+        }
+
+        if let Some(backend) = self.backend {
+            args.extend(["--backend".to_string(), backend.to_string()]);
+
+            let sanitizer = self.get_option("b_sanitize").unwrap_or("none");
+            let visual_studio = matches!(
+                backend,
+                Backend::Vs | Backend::Vs2017 | Backend::Vs2019 | Backend::Vs2022
+            );
+            if visual_studio && !matches!(sanitizer, "none" | "address") {
+                self.print(&format!(
+                    "cargo:warning=Sanitizer '{sanitizer}' is not supported by {backend}"
+                ));
+            }
+        }
+
+        if let Some(wrap_mode) = self.wrap_mode {
+            args.extend(["--wrap-mode".to_string(), wrap_mode.to_string()]);
+        }
+
+        if self.vsenv {
+            args.push("--vsenv".to_string());
+        }
+
+        let options = self
+            .effective_options()
+            .into_iter()
+            .map(|(key, value)| format!("-D{}={}", key, value));
+
+        args.extend(options);
+
+        // Switch to OsString when dealing with paths
+        let mut os_args: Vec<OsString> = args.into_iter().map(OsString::from).collect();
+
+        // Machine files
+        for native_file in &self.native_files {
+            os_args.extend([OsString::from("--native-file"), native_file.into()]);
+        }
+        for cross_file in &self.cross_files {
+            os_args.extend([OsString::from("--cross-file"), cross_file.into()]);
+        }
+
+        // Install prefix, which meson requires to be absolute
+        let prefix = match self.prefix {
+            Some(ref prefix) => absolute(prefix)?,
+            None => absolute(&self.install_dir()?)?,
+        };
+        os_args.extend([OsString::from("--prefix"), prefix.into()]);
+
+        // Install layout
+        let dirs = [
+            ("--libdir", &self.libdir),
+            ("--bindir", &self.bindir),
+            ("--includedir", &self.includedir),
+            ("--datadir", &self.datadir),
+        ];
+        for (flag, dir) in dirs {
+            if let Some(dir) = dir {
+                os_args.extend([OsString::from(flag), dir.into()]);
+            }
+        }
+
+        os_args.extend(self.extra_setup_args.iter().cloned());
+        os_args.extend(
+            setup_args_from_env(|var| self.var(var))
+                .into_iter()
+                .map(OsString::from),
+        );
+
+        // Finally, build and source directories
+        os_args.extend([self.build_dir()?.into(), source_dir.into()]);
+
+        Ok(os_args)
+    }
+
+    /// Arguments passed to meson to compile the project in `build_dir`.
+    fn compile_args(&self, build_dir: &Path) -> Vec<OsString> {
+        self.compile_args_for(build_dir, &self.targets)
+    }
+
+    /// Same as [`Config::compile_args`], but compiles only `targets`.
+    fn compile_args_for(&self, build_dir: &Path, targets: &[String]) -> Vec<OsString> {
+        let mut args = vec![
+            OsString::from("compile"),
+            OsString::from("-C"),
+            build_dir.into(),
+        ];
+
+        if let Some(jobs) = self.jobs() {
+            args.extend([OsString::from("-j"), jobs.to_string().into()]);
+        }
+
+        if self.verbose {
+            args.push("-v".into());
+        }
+
+        args.extend(targets.iter().map(OsString::from));
+
+        args
+    }
+
+    /// Command installing the project compiled in `build_dir`.
+    ///
+    /// The subprojects of the configured project are introspected if filtered, except in
+    /// dry-run mode which only prints the introspection command.
+    fn install_command(&self, build_dir: &Path) -> Result<Command> {
+        let mut command = self.meson_command();
+        command.arg("install");
+        command.arg("-C");
+        command.arg(build_dir);
+
+        if self.strip {
+            command.arg("--strip");
+        }
+
+        if !self.install_tags.is_empty() {
+            command.args(["--tags", &self.install_tags.join(",")]);
+        }
+
+        if self.subproject_filter.is_some() && self.dry_run {
+            let introspect_command = self.introspect_command("--projectinfo")?;
+            self.print(&format!(
+                "Dry run: {}",
+                command::command_line(&introspect_command)
+            ));
+        } else if self.subproject_filter.is_some() {
+            let output = self.introspect("--projectinfo")?;
+            let subprojects = introspect::parse_subprojects(&output)?;
+            if let Some(skipped) = self.skipped_subprojects(&subprojects) {
+                command.args(["--skip-subprojects", &skipped]);
+            }
+        }
+
+        if let Some(ref destdir) = self.destdir {
+            command.env("DESTDIR", destdir);
+        }
+
+        Ok(command)
+    }
+
+    /// The comma separated `subprojects` excluded by the subproject filter, `None` if all are
+    /// installed.
+    fn skipped_subprojects(&self, subprojects: &[String]) -> Option<String> {
+        let filter = self.subproject_filter.as_ref()?;
+        let skipped: Vec<&str> = subprojects
+            .iter()
+            .filter(|subproject| !filter.contains(subproject))
+            .map(String::as_str)
+            .collect();
+
+        if skipped.is_empty() {
+            None
+        } else {
+            Some(skipped.join(","))
+        }
+    }
+
+    /// Start a new build process for the meson project in `source_dir`
+    ///
+    /// Same as [`Config::compile`] followed by [`Config::install`],
+    /// unless disabled by [`Config::set_install`].
+    pub fn build(self, source_dir: &Path) -> Result<()> {
+        self.run_build(source_dir)?;
+        Ok(())
+    }
+
+    /// Runs the steps of [`Config::build`], returns whether `meson setup` ran.
+    fn run_build(&self, source_dir: &Path) -> Result<bool> {
+        let configured = self.configure(source_dir)?;
+        self.compile_configured(source_dir)?;
+
+        if self.install {
+            self.install()?;
+        }
+
+        Ok(configured)
+    }
+
+    /// Same as [`Config::build`], but never runs `meson setup`.
+    ///
+    /// For build directories configured beforehand, returns [`Error::NotConfigured`] otherwise.
+    pub fn build_incremental(&self, source_dir: &Path) -> Result<()> {
+        if !self.is_configured() {
+            return Err(Error::NotConfigured(self.build_dir()?));
+        }
+
+        self.compile_configured(source_dir)?;
+
+        if self.install {
+            self.install()?;
+        }
+
+        Ok(())
+    }
+
+    /// Same as [`Config::build`], but returns the paths and files of the build.
+    ///
+    /// No installed file is reported in dry-run mode, as nothing is installed.
+    pub fn build_with_outcome(self, source_dir: &Path) -> Result<BuildOutcome> {
+        let configured = self.run_build(source_dir)?;
+        self.build_outcome(configured)
+    }
+
+    /// The outcome of a build which ran `meson setup` if `configured`.
+    fn build_outcome(&self, configured: bool) -> Result<BuildOutcome> {
+        let installed_files = if self.install && !self.dry_run {
+            self.installed_files()?
+        } else {
+            Vec::new()
+        };
+
+        Ok(BuildOutcome {
+            build_dir: self.build_dir()?,
+            install_dir: self.install_dir()?,
+            configured,
+            installed_files,
+        })
+    }
+
+    /// Configures and compiles the meson project in `source_dir` without installing it.
+    pub fn compile(&self, source_dir: &Path) -> Result<()> {
+        self.configure(source_dir)?;
+        self.compile_configured(source_dir)
+    }
+
+    /// Compiles the already configured project in `source_dir`.
+    fn compile_configured(&self, source_dir: &Path) -> Result<()> {
+        let mut build_command = self.compile_command(source_dir)?;
+        self.run(&mut build_command, |code, stderr| {
+            Error::MesonBuildUnsuccessfully { code, stderr }
+        })?;
+
+        Ok(())
+    }
+
+    /// The `meson compile` command for the project in `source_dir`.
+    fn compile_command(&self, source_dir: &Path) -> Result<Command> {
+        if self.subproject_filter.is_some() && self.targets.is_empty() {
+            self.print(
+                "cargo:warning=All subprojects are compiled despite the subproject filter, \
+                use set_targets to compile only the needed targets",
+            );
+        }
+
+        let build_dir = self.build_dir()?;
+        if !self.dry_run {
+            std::fs::create_dir_all(&build_dir)?;
+        }
+
+        let mut command = self.meson_command();
+        command.current_dir(source_dir);
+        command.args(self.compile_args(&build_dir));
+        Ok(command)
+    }
+
+    /// Cleans and recompiles `target` of the already configured project.
+    ///
+    /// An escape hatch for stale outputs missed by the dependency tracking. With the ninja
+    /// backend only the outputs of `target` and the files they are built from are removed
+    /// using `ninja -t clean`. Other backends clean the whole build directory.
+    ///
+    /// In dry-run mode, the outputs are not introspected and the ninja clean command is not
+    /// printed.
+    pub fn rebuild_target(&self, target: &str) -> Result<()> {
+        let build_dir = self.build_dir()?;
+
+        let mut clean_command = match self.backend {
+            None | Some(Backend::Ninja) if self.dry_run => {
+                let introspect_command = self.introspect_command("--targets")?;
+                self.print(&format!(
+                    "Dry run: {}",
+                    command::command_line(&introspect_command)
+                ));
+                None
+            }
+            None | Some(Backend::Ninja) => {
+                let target_info = self
+                    .introspect_targets()?
+                    .into_iter()
+                    .find(|t| t.name == target)
+                    .ok_or_else(|| Error::TargetNotFound(target.to_string()))?;
+
+                let ninja = match self.ninja_path {
+                    Some(ref ninja) => ninja.clone(),
+                    None => crate::find_ninja()?,
+                };
+                let mut command = Command::new(ninja);
+                command.arg("-C").arg(&build_dir).args(["-t", "clean"]);
+                for output in &target_info.filename {
+                    command.arg(relative_to_build_dir(output, &build_dir)?);
+                }
+                Some(command)
+            }
+            Some(_) => {
+                let mut command = self.meson_command();
+                command
+                    .arg("compile")
+                    .arg("-C")
+                    .arg(&build_dir)
+                    .arg("--clean");
+                Some(command)
+            }
+        };
+        if let Some(ref mut clean_command) = clean_command {
+            self.run(clean_command, |code, stderr| {
+                Error::MesonBuildUnsuccessfully { code, stderr }
+            })?;
+        }
+
+        let mut compile_command = self.meson_command();
+        compile_command.args(self.compile_args_for(&build_dir, &[target.to_string()]));
+        self.run(&mut compile_command, |code, stderr| {
+            Error::MesonBuildUnsuccessfully { code, stderr }
+        })?;
+
+        Ok(())
+    }
+
+    /// Installs the project compiled by [`Config::compile`] into [`Config::install_dir`].
+    pub fn install(&self) -> Result<()> {
+        let mut install_command = self.prepare_install()?;
+        self.run(&mut install_command, |code, stderr| {
+            Error::MesonBuildUnsuccessfully { code, stderr }
+        })?;
+
+        Ok(())
+    }
+
+    /// The command run by [`Config::install`], after creating the install directory unless in
+    /// dry-run mode.
+    fn prepare_install(&self) -> Result<Command> {
+        if !self.dry_run {
+            std::fs::create_dir_all(self.install_dir()?)?;
+        }
+
+        self.install_command(&self.build_dir()?)
+    }
+
+    /// Lists the files installed by [`Config::install`] as recorded in
+    /// `meson-logs/install-log.txt` of the build directory.
+    pub fn installed_files(&self) -> Result<Vec<PathBuf>> {
+        let install_log = self.build_dir()?.join("meson-logs").join("install-log.txt");
+        let log = match std::fs::read_to_string(&install_log) {
+            Ok(log) => log,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                return Err(Error::InstallLogNotFound(install_log))
+            }
+            Err(err) => return Err(err.into()),
+        };
+
+        Ok(parse_install_log(&log))
+    }
+
+    /// Runs the test suite of the configured project with `meson test`.
+    ///
+    /// Not run by [`Config::build`], should be called after it.
+    pub fn run_tests(&self) -> Result<()> {
+        self.run_tests_filtered(None, &[])
+    }
+
+    /// Runs the test suite like [`Config::run_tests`], and returns the result of each test
+    /// recorded by meson in `meson-logs/testlog.json`.
+    ///
+    /// Failing tests are reported in the results instead of [`Error::MesonTestsFailed`]. The
+    /// results are empty in dry-run mode, as no test runs.
+    pub fn run_tests_json(&self) -> Result<Vec<TestResult>> {
+        let status = self.run_tests();
+        if self.dry_run {
+            return status.map(|()| Vec::new());
+        }
+
+        let test_log = self.build_dir()?.join("meson-logs").join("testlog.json");
+        let results = std::fs::read_to_string(test_log)
+            .map_err(Error::from)
+            .and_then(|log| test_log::parse_test_log(&log));
+
+        match (status, results) {
+            (Ok(()) | Err(Error::MesonTestsFailed(_)), Ok(results)) => Ok(results),
+            (Err(err), _) | (Ok(()), Err(err)) => Err(err),
+        }
+    }
+
+    /// Runs the tests of the configured project from `suite` or with given `names`.
+    ///
+    /// All tests are run if neither is specified.
+    pub fn run_tests_filtered(&self, suite: Option<&str>, names: &[&str]) -> Result<()> {
+        let mut test_command = self.meson_command();
+        test_command.args(self.test_args(&self.build_dir()?, suite, names));
+
+        self.run(&mut test_command, |code, _| Error::MesonTestsFailed(code))?;
+
+        Ok(())
+    }
+
+    /// Arguments passed to meson to run tests of the project in `build_dir`.
+    fn test_args(&self, build_dir: &Path, suite: Option<&str>, names: &[&str]) -> Vec<OsString> {
+        let mut args = vec![
+            OsString::from("test"),
+            OsString::from("-C"),
+            build_dir.into(),
+        ];
+
+        if let Some(suite) = suite {
+            args.extend([OsString::from("--suite"), suite.into()]);
+        }
+
+        args.extend(names.iter().map(OsString::from));
+
+        args
+    }
+
+    /// Generates coverage reports of the compiled project into the `meson-logs` folder of
+    /// [`Config::build_dir`] by building the `coverage` target.
+    ///
+    /// Requires building with [`Config::set_coverage`] and running the instrumented code first,
+    /// e.g. using [`Config::run_tests`]. Meson generates the reports using `gcovr` or
+    /// `lcov` with `genhtml`, which must be installed.
+    pub fn coverage_report(&self) -> Result<()> {
+        let mut command = self.meson_command();
+        command.args(self.coverage_args(&self.build_dir()?));
+
+        self.run(&mut command, |code, stderr| {
+            Error::MesonBuildUnsuccessfully { code, stderr }
+        })?;
+
+        Ok(())
+    }
+
+    /// Arguments passed to meson to generate coverage reports of the project in `build_dir`.
+    fn coverage_args(&self, build_dir: &Path) -> Vec<OsString> {
+        vec![
+            OsString::from("compile"),
+            OsString::from("-C"),
+            build_dir.into(),
+            OsString::from("coverage"),
+        ]
+    }
+
+    /// Creates a source tarball of the compiled project using `meson dist` and returns its path
+    /// in the `meson-dist` folder of [`Config::build_dir`].
+    ///
+    /// The archive is `xztar`, meson's default. Meson runs the tests of the project before.
+    pub fn dist(&self) -> Result<PathBuf> {
+        let mut archives = self.dist_with_formats(&["xztar"])?;
+        Ok(archives.remove(0))
+    }
+
+    /// Same as [`Config::dist`], but creates an archive for each of `formats`
+    /// (`xztar`, `gztar`, `zip`), returned in the same order.
+    pub fn dist_with_formats(&self, formats: &[&str]) -> Result<Vec<PathBuf>> {
+        let build_dir = self.build_dir()?;
+        let mut command = self.meson_command();
+        command.args(self.dist_args(&build_dir, formats));
+
+        self.run(&mut command, |code, stderr| {
+            Error::MesonBuildUnsuccessfully { code, stderr }
+        })?;
+
+        let dist_dir = build_dir.join("meson-dist");
+        formats
+            .iter()
+            .map(|format| find_dist_archive(&dist_dir, format))
+            .collect()
+    }
+
+    /// Arguments passed to meson to create the `formats` archives of the project in `build_dir`.
+    fn dist_args(&self, build_dir: &Path, formats: &[&str]) -> Vec<OsString> {
+        let mut args = vec![
+            OsString::from("dist"),
+            OsString::from("-C"),
+            build_dir.into(),
+        ];
+        if !formats.is_empty() {
+            args.extend([OsString::from("--formats"), formats.join(",").into()]);
+        }
+        args
+    }
+
+    /// Edits the `meson.build` files of the project in `source_dir` using `meson rewrite`,
+    /// e.g. `["kwargs", "set", "project", "/", "version", "1.2.0"]`.
+    ///
+    /// An escape hatch for automation modifying the build definition: `args` are passed as is.
+    pub fn rewrite(&self, source_dir: &Path, args: &[&str]) -> Result<()> {
+        let mut command = self.meson_command();
+        command.args(self.rewrite_args(source_dir, args));
+
+        self.run(&mut command, |code, stderr| {
+            Error::MesonExitedUnsuccessfully { code, stderr }
+        })?;
+
+        Ok(())
+    }
+
+    /// Arguments passed to meson to rewrite the project in `source_dir`.
+    fn rewrite_args(&self, source_dir: &Path, args: &[&str]) -> Vec<OsString> {
+        let mut rewrite_args = vec![
+            OsString::from("rewrite"),
+            OsString::from("--sourcedir"),
+            source_dir.into(),
+        ];
+        rewrite_args.extend(args.iter().map(OsString::from));
+        rewrite_args
+    }
+
+    /// Lists the build targets of the configured project using `meson introspect --targets`.
+    pub fn introspect_targets(&self) -> Result<Vec<Target>> {
+        let output = self.introspect("--targets")?;
+        introspect::parse_targets(&output)
+    }
+
+    /// Lists the external dependencies of the configured project using
+    /// `meson introspect --dependencies`.
+    pub fn introspect_dependencies(&self) -> Result<Vec<Dependency>> {
+        let output = self.introspect("--dependencies")?;
+        introspect::parse_dependencies(&output)
+    }
+
+    /// Lists the dependencies declared by the project in `source_dir` using
+    /// `meson introspect --dependencies`, without configuring it.
+    ///
+    /// Unlike [`Config::introspect_dependencies`], tells which dependencies are required, e.g.
+    /// to know what to install before building.
+    pub fn introspect_declared_dependencies(
+        &self,
+        source_dir: &Path,
+    ) -> Result<Vec<DeclaredDependency>> {
+        let mut command = self.meson_command();
+        command
+            .arg("introspect")
+            .arg("--dependencies")
+            .arg(source_dir.join("meson.build"));
+        let output = command::output(&mut command, self.timeout, |code, stderr| {
+            Error::MesonExitedUnsuccessfully { code, stderr }
+        })?;
+
+        introspect::parse_declared_dependencies(&output.stdout)
+    }
+
+    /// Reads the options of the configured project using `meson introspect --buildoptions`.
+    ///
+    /// Values are formatted as they are passed to `-D`, e.g. `true` or `['a','b']` for arrays.
+    pub fn configured_options(&self) -> Result<HashMap<String, String>> {
+        let output = self.introspect("--buildoptions")?;
+        introspect::parse_build_options(&output)
+    }
+
+    /// Applies the options which differ from the configured ones using `meson configure`,
+    /// returns whether any option changed.
+    ///
+    /// Faster than [`Config::set_reconfigure`] for option-only changes. Only the options and
+    /// the buildtype are compared and recorded as applied, other changes like machine files are
+    /// left for the next `meson setup`.
+    pub fn sync_options(&self) -> Result<bool> {
+        let profile = self.validated_profile()?;
+        let configured = self.configured_options()?;
+
+        let mut desired = self.effective_options();
+        if !profile.is_empty() {
+            desired.insert("buildtype".to_string(), profile.to_string());
+        }
+
+        let drifted: Vec<String> = desired
+            .into_iter()
+            .filter(|(key, value)| configured.get(key) != Some(value))
+            .map(|(key, value)| format!("-D{key}={value}"))
+            .collect();
+        if drifted.is_empty() {
+            return Ok(false);
+        }
+
+        let mut command = self.meson_command();
+        command
+            .arg("configure")
+            .arg(self.build_dir()?)
+            .args(drifted);
+        self.run(&mut command, |code, stderr| {
+            self.configure_failed(code, stderr)
+        })?;
+
+        if !self.dry_run {
+            self.save_options_state()?;
+        }
+        Ok(true)
+    }
+
+    /// Runs meson with `args`, e.g. for subcommands not wrapped by this crate, and returns its
+    /// captured output.
+    ///
+    /// Meson runs in [`Config::build_dir`] if it exists. Fails with
+    /// [`Error::MesonExitedUnsuccessfully`] if meson exits with an error.
+    pub fn run_subcommand(&self, args: &[&OsStr]) -> Result<Output> {
+        let mut command = self.meson_command();
+        command.args(args);
+        if let Ok(build_dir) = self.build_dir() {
+            if build_dir.is_dir() {
+                command.current_dir(build_dir);
+            }
+        }
+
+        command::output(&mut command, self.timeout, |code, stderr| {
+            Error::MesonExitedUnsuccessfully { code, stderr }
+        })
+    }
+
+    /// Runs `meson introspect` on the build directory and returns its standard output.
+    fn introspect(&self, what: &str) -> Result<Vec<u8>> {
+        let mut command = self.introspect_command(what)?;
+        let output = command::output(&mut command, self.timeout, |code, stderr| {
+            Error::MesonExitedUnsuccessfully { code, stderr }
+        })?;
+
+        Ok(output.stdout)
+    }
+
+    /// The `meson introspect` command listing `what` for the configured project.
+    fn introspect_command(&self, what: &str) -> Result<Command> {
+        let mut command = self.meson_command();
+        command.arg("introspect");
+        command.arg(self.build_dir()?);
+        command.arg(what);
+        Ok(command)
+    }
+
+    /// Returns the version of Meson installed on this system.
+    fn get_version_of_meson(
+        meson_path: impl AsRef<Path>,
+        meson_args: &[OsString],
+    ) -> Result<Version> {
+        let mut command = Command::new(meson_path.as_ref());
+
+        command.args(meson_args);
+        command.arg("--version");
+
+        let output = command::output(&mut command, None, |code, stderr| {
+            Error::MesonExitedUnsuccessfully { code, stderr }
+        })?;
+
+        let version_raw = core::str::from_utf8(&output.stdout)?.trim();
+        let version = Version::parse(version_raw)?;
+
+        Ok(version)
+    }
+
+    /// Ways to run meson, in order of preference.
+    ///
+    /// A meson set by the environment is used as is. Otherwise `meson` is searched in `PATH`,
+    /// and on Windows `meson.exe` and the `mesonbuild` python module are tried as well.
+    fn meson_candidates() -> Vec<Launcher> {
+        let meson = program_from_env("MESON", "meson");
+        if meson != Path::new("meson") || !cfg!(windows) {
+            return vec![(meson, Vec::new())];
+        }
+
+        vec![
+            (meson, Vec::new()),
+            ("meson.exe".into(), Vec::new()),
+            ("python".into(), mesonbuild_module()),
+            ("py".into(), mesonbuild_module()),
+        ]
+    }
+
+    /// The number of jobs passed to meson, `None` if meson should decide.
+    fn jobs(&self) -> Option<usize> {
+        let jobs = match self.jobs {
+            Some(jobs) => jobs,
+            None => self.var("NUM_JOBS")?.parse().ok()?,
+        };
+
+        if jobs == 0 {
+            None
+        } else {
+            Some(jobs)
+        }
+    }
+
+    /// The environment variable `name` provided by Cargo.
+    fn var_os(&self, name: &str) -> Option<OsString> {
+        match self.env_lookup {
+            Some(EnvLookup(ref lookup)) => lookup(name),
+            None => env::var_os(name),
+        }
+    }
+
+    /// Same as [`Config::var_os`], `None` if the value is not unicode.
+    fn var(&self, name: &str) -> Option<String> {
+        self.var_os(name)?.into_string().ok()
+    }
+
+    /// Prints `message` as a `cargo:info=` line unless quiet.
+    fn info(&self, message: &str) {
+        if self.quiet {
+            return;
+        }
+
+        self.print(&format!("cargo:info={message}"));
+    }
+
+    /// Prints `line` for Cargo.
+    fn print(&self, line: &str) {
+        match self.printer {
+            Some(Printer(ref print)) => print(line),
+            None => println!("{line}"),
+        }
+    }
+
+    /// The profile set by [`Config::set_profile`], else the one matching `PROFILE`.
+    ///
+    /// See [`Config::report_profile`] for the message about a missing or unknown `PROFILE`.
+    fn profile(&self) -> &str {
+        match self.profile {
+            Some(ref profile) => profile,
+            None => match self.var("PROFILE").as_deref() {
+                Some("debug") => "debug",
+                _ => "release",
+            },
+        }
+    }
+
+    /// [`Config::profile`], failing with [`Error::InvalidBuildtype`] if meson does not know it.
+    ///
+    /// An empty profile is returned as is, it leaves the buildtype to meson.
+    fn validated_profile(&self) -> Result<&str> {
+        let profile = self.profile();
+        if !profile.is_empty() && !BUILDTYPES.contains(&profile) {
+            return Err(Error::InvalidBuildtype(profile.to_string()));
+        }
+        Ok(profile)
+    }
+
+    /// Tells when [`Config::profile`] falls back to release, printed once per `meson setup`.
+    fn report_profile(&self) {
+        if self.profile.is_some() {
+            return;
+        }
+
+        match self.var("PROFILE").as_deref() {
+            Some("debug" | "release") => {}
+            None => self.info("PROFILE is not set, using release as default."),
+            Some(profile) => self.print(&format!(
+                "cargo:warning=PROFILE '{profile}' is unknown.
+                        Using release as default. Please override profile using set_profile"
+            )),
+        }
+    }
+}
+
+/// `output` reported by meson relative to `build_dir`, as ninja names it.
+///
+/// Meson reports absolute paths, while `build_dir` may be relative or contain symbolic links.
+fn relative_to_build_dir(output: &Path, build_dir: &Path) -> Result<PathBuf> {
+    let absolute_build_dir = env::current_dir()?.join(build_dir);
+    if let Some(relative) = [build_dir, &absolute_build_dir]
+        .into_iter()
+        .find_map(|dir| output.strip_prefix(dir).ok())
+    {
+        return Ok(relative.to_owned());
+    }
+
+    // The output may not exist yet, so its directory is resolved instead
+    let resolved = || {
+        let build_dir = build_dir.canonicalize().ok()?;
+        let parent = output.parent()?.canonicalize().ok()?;
+        let relative = parent.strip_prefix(build_dir).ok()?;
+        Some(relative.join(output.file_name()?))
+    };
+    Ok(resolved().unwrap_or_else(|| output.to_owned()))
+}
+
+/// Buildtypes accepted by `meson setup --buildtype`.
+const BUILDTYPES: [&str; 6] = [
+    "plain",
+    "debug",
+    "debugoptimized",
+    "release",
+    "minsize",
+    "custom",
+];
+
+/// Python arguments running meson.
+fn mesonbuild_module() -> Vec<OsString> {
+    vec![OsString::from("-m"), OsString::from("mesonbuild")]
+}
+
+/// How meson is run: the program followed by the arguments selecting meson.
+type Launcher = (PathBuf, Vec<OsString>);
+
+/// The meson found by [`Config::find_system_meson`] for its candidates, and its version.
+type VersionCache = HashMap<Vec<Launcher>, (Launcher, Version)>;
+
+fn version_cache() -> &'static Mutex<VersionCache> {
+    static CACHE: OnceLock<Mutex<VersionCache>> = OnceLock::new();
+    CACHE.get_or_init(Default::default)
+}
+
+/// The program set by `<VAR>_<TARGET_UPPER_CASE>` or `<VAR>`, otherwise `default` to search in
+/// `PATH`.
+pub(crate) fn program_from_env(var: &str, default: &str) -> PathBuf {
+    let target_specific_env = target_specific_env_name(var, |var| env::var(var).ok());
+    if let Some(program) = target_specific_env.and_then(env::var_os) {
+        return program.into();
+    }
+
+    if let Some(program) = env::var_os(var) {
+        return program.into();
+    }
+
+    default.into()
+}
+
+/// Extra `meson setup` arguments from `MESON_ARGS_<TARGET_UPPER_CASE>`, or `MESON_ARGS` if the
+/// target specific variable is not set.
+fn setup_args_from_env(lookup: impl Fn(&str) -> Option<String>) -> Vec<String> {
+    let args = target_specific_env_name("MESON_ARGS", &lookup)
+        .and_then(|var| lookup(&var))
+        .or_else(|| lookup("MESON_ARGS"));
+
+    args.as_deref().map(split_args).unwrap_or_default()
+}
+
+/// Splits `args` into words like a POSIX shell, without any expansions.
+///
+/// Words are separated by whitespace. Single quotes preserve everything up to the closing
+/// quote, double quotes preserve everything except `\"` and `\\` escapes, and a backslash
+/// outside of quotes escapes the next character.
+fn split_args(args: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = args.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => words.extend(word.take()),
+            '\'' => {
+                let word = word.get_or_insert_with(String::new);
+                word.extend(chars.by_ref().take_while(|&c| c != '\''));
+            }
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => match chars.next() {
+                            Some(c @ ('"' | '\\')) => word.push(c),
+                            Some(c) => word.extend(['\\', c]),
+                            None => word.push('\\'),
+                        },
+                        c => word.push(c),
+                    }
+                }
+            }
+            '\\' => word.get_or_insert_with(String::new).extend(chars.next()),
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+
+    words
+}
+
+/// Name of the `<PREFIX>_<TARGET_UPPER_CASE>` environment variable, if `TARGET` is set.
+fn target_specific_env_name(
+    prefix: &str,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> Option<String> {
+    let target = lookup("TARGET")?;
+    let target_upper_case = target.to_uppercase().replace('-', "_");
+    Some(format!("{prefix}_{target_upper_case}"))
+}
+
+/// Maps Cargo's `opt-level` and `debug` settings into the closest meson buildtype.
+fn buildtype_from_cargo(opt_level: &str, debug: bool) -> &'static str {
+    match (opt_level, debug) {
+        ("0", true) => "debug",
+        ("0", false) => "plain",
+        ("s" | "z", _) => "minsize",
+        (_, true) => "debugoptimized",
+        (_, false) => "release",
+    }
+}
+
+/// `path` made absolute relative to the current directory.
+fn absolute(path: &Path) -> Result<PathBuf> {
+    if path.is_absolute() {
+        Ok(path.to_owned())
+    } else {
+        Ok(env::current_dir()?.join(path))
+    }
+}
+
+/// The most recent archive of `format` created by `meson dist` in `dist_dir`.
+fn find_dist_archive(dist_dir: &Path, format: &str) -> Result<PathBuf> {
+    let extension = match format {
+        "xztar" => ".tar.xz",
+        "gztar" => ".tar.gz",
+        "bztar" => ".tar.bz2",
+        "zip" => ".zip",
+        _ => {
+            return Err(Error::DistArchiveNotFound {
+                dir: dist_dir.to_owned(),
+                format: format.to_string(),
+            })
+        }
+    };
+
+    let mut newest: Option<(std::time::SystemTime, PathBuf)> = None;
+    for entry in std::fs::read_dir(dist_dir)? {
+        let entry = entry?;
+        if !entry.file_name().to_string_lossy().ends_with(extension) {
+            continue;
+        }
+
+        let modified = entry.metadata()?.modified()?;
+        if newest.as_ref().map_or(true, |(time, _)| modified >= *time) {
+            newest = Some((modified, entry.path()));
+        }
+    }
+
+    newest
+        .map(|(_, path)| path)
+        .ok_or_else(|| Error::DistArchiveNotFound {
+            dir: dist_dir.to_owned(),
+            format: format.to_string(),
+        })
+}
+
+/// Whether `dir` contains an entry with `extension`, e.g. a Visual Studio solution.
+fn contains_extension(dir: &Path, extension: &str) -> bool {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return false;
+    };
+
+    entries
+        .filter_map(|entry| entry.ok())
+        .any(|entry| entry.path().extension().is_some_and(|ext| ext == extension))
+}
+
+/// Parses meson's install log: one installed path per line, `#` starts a comment.
+fn parse_install_log(log: &str) -> Vec<PathBuf> {
+    log.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let unquoted = line
+                .strip_prefix('\'')
+                .and_then(|line| line.strip_suffix('\''));
+            PathBuf::from(unquoted.unwrap_or(line))
+        })
+        .collect()
+}
+
+/// Encodes `value` as a JSON string literal.
+fn json_string(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len() + 2);
+    encoded.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => encoded.push_str("\\\""),
+            '\\' => encoded.push_str("\\\\"),
+            c if c.is_control() => encoded.push_str(&format!("\\u{:04x}", c as u32)),
+            c => encoded.push(c),
+        }
+    }
+    encoded.push('"');
+    encoded
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    use std::{ffi::OsStr, sync::MutexGuard};
+
+    /// The lines `config` prints for Cargo while running `f`.
+    fn printed_by(mut config: Config, f: impl FnOnce(Config)) -> Vec<String> {
+        let printed = Arc::new(Mutex::new(Vec::new()));
+        let recorded = printed.clone();
+        config.printer = Some(Printer(Arc::new(move |line| {
+            recorded.lock().unwrap().push(line.to_string())
+        })));
+        f(config);
+
+        let printed = printed.lock().unwrap();
+        printed.clone()
+    }
+
+    /// Serializes tests touching the process environment.
+    fn env_lock() -> MutexGuard<'static, ()> {
+        static ENV_LOCK: Mutex<()> = Mutex::new(());
+        ENV_LOCK.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Runs `f` with environment variables set or removed, restoring them afterwards.
+    pub(crate) fn with_env<T>(vars: &[(&str, Option<&str>)], f: impl FnOnce() -> T) -> T {
+        let _lock = env_lock();
+
+        let saved: Vec<_> = vars
+            .iter()
+            .map(|(name, _)| (*name, env::var_os(name)))
+            .collect();
+        for (name, value) in vars {
+            match value {
+                Some(value) => env::set_var(name, value),
+                None => env::remove_var(name),
+            }
+        }
+
+        let result = f();
+
+        for (name, value) in saved {
+            match value {
+                Some(value) => env::set_var(name, value),
+                None => env::remove_var(name),
+            }
+        }
+
+        result
+    }
+
+    /// A config that does not need a meson installation.
+    ///
+    /// The config reads no variable from the process environment, see [`with_vars`].
+    pub(crate) fn test_config() -> Config {
+        let config = Config::new("meson".into(), Version::new(1, 0, 0));
+        with_vars(config, &[]).with_out_path(Path::new("out"))
+    }
+
+    /// `config` reading the variables provided by Cargo from `vars` only.
+    pub(crate) fn with_vars(mut config: Config, vars: &[(&str, &str)]) -> Config {
+        let vars: HashMap<String, OsString> = vars
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.into()))
+            .collect();
+        config.env_lookup = Some(EnvLookup(Arc::new(move |name| vars.get(name).cloned())));
+        config
+    }
+
+    /// A config running a fake meson shell script with commands from `script`.
+    ///
+    /// The config uses `dir` as the output path, and `dir` can be used as the source directory.
+    #[cfg(unix)]
+    pub(crate) fn fake_meson_script_config(dir: &Path, script: &str) -> Config {
+        use std::os::unix::fs::PermissionsExt;
+
+        let meson = dir.join("meson");
+        std::fs::write(&meson, format!("#!/bin/sh\n{script}\n")).unwrap();
+        std::fs::set_permissions(&meson, std::fs::Permissions::from_mode(0o755)).unwrap();
+        std::fs::write(dir.join("meson.build"), "project('fake')\n").unwrap();
+
+        let mut config = test_config().with_out_path(dir).with_profile("release");
+        config.meson_path = meson;
+        config
+    }
+
+    /// A config running a fake meson script which logs its arguments into `dir/meson.log`.
+    ///
+    /// The config uses `dir` as the output path.
+    #[cfg(unix)]
+    fn fake_meson_config(dir: &Path) -> Config {
+        let log = dir.join("meson.log");
+        let script = format!(
+            "echo \"$@\" >> '{}'\n[ \"$1\" = --version ] && echo 1.0.0\nexit 0",
+            log.display()
+        );
+        fake_meson_script_config(dir, &script)
+    }
+
+    /// Invocations of the fake meson created by [`fake_meson_config`].
+    #[cfg(unix)]
+    fn fake_meson_log(dir: &Path) -> Vec<String> {
+        match std::fs::read_to_string(dir.join("meson.log")) {
+            Ok(log) => log.lines().map(str::to_string).collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    /// `-D` arguments passed to `meson setup`.
+    fn option_args(config: &Config) -> Vec<String> {
+        config
+            .setup_args(Path::new("src"))
+            .unwrap()
+            .into_iter()
+            .filter_map(|arg| arg.into_string().ok())
+            .filter(|arg| arg.starts_with("-D"))
+            .collect()
+    }
+
+    #[test]
+    fn test_builder_matches_setters() {
+        let source_dir = Path::new("src");
+
+        let mut stepwise = test_config();
+        stepwise.set_profile("release");
+        stepwise.set_option("foo", "bar");
+        stepwise.set_option("baz", "qux");
+        stepwise.set_native_file(Path::new("native.ini"));
+        stepwise.set_cross_file(Path::new("cross.ini"));
+        stepwise.set_out_path(Path::new("target"));
+
+        let chained = test_config()
+            .with_profile("release")
+            .with_option("foo", "bar")
+            .with_option("baz", "qux")
+            .with_native_file(Path::new("native.ini"))
+            .with_cross_file(Path::new("cross.ini"))
+            .with_out_path(Path::new("target"));
+
+        assert_eq!(
+            stepwise.setup_args(source_dir).unwrap(),
+            chained.setup_args(source_dir).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_backend_arg() {
+        let source_dir = Path::new("src");
+        let mut config = test_config().with_profile("release");
+
+        let args = config.setup_args(source_dir).unwrap();
+        assert!(!args.iter().any(|arg| arg == "--backend"));
+
+        config.set_backend(Backend::Vs2022);
+        let args = config.setup_args(source_dir).unwrap();
+        let position = args.iter().position(|arg| arg == "--backend").unwrap();
+        assert_eq!(args[position + 1], "vs2022");
+    }
+
+    #[test]
+    fn test_jobs_arg() {
+        let build_dir = Path::new("build");
+        let jobs_arg = |config: &Config| {
+            let args = config.compile_args(build_dir);
+            let position = args.iter().position(|arg| arg == "-j")?;
+            Some(args[position + 1].clone())
+        };
+
+        assert_eq!(jobs_arg(&test_config()), None);
+        assert_eq!(jobs_arg(&test_config().with_jobs(4)), Some("4".into()));
+        assert_eq!(jobs_arg(&test_config().with_jobs(0)), None);
+
+        let config = with_vars(test_config(), &[("NUM_JOBS", "8")]);
+        assert_eq!(jobs_arg(&config), Some("8".into()));
+        assert_eq!(jobs_arg(&config.clone().with_jobs(2)), Some("2".into()));
+        assert_eq!(jobs_arg(&config.with_jobs(0)), None);
+    }
+
+    #[test]
+    fn test_verbose_arg() {
+        let verbose = |config: &Config| {
+            let args = config.compile_args(Path::new("build"));
+            args.iter().any(|arg| arg == "-v")
+        };
+
+        assert!(!verbose(&test_config()));
+        assert!(verbose(&test_config().with_verbose(true)));
+    }
+
+    #[test]
+    fn test_compile_targets() {
+        let build_dir = Path::new("build");
+        let args = test_config().compile_args(build_dir);
+        assert_eq!(args, ["compile", "-C", "build"]);
+
+        let args = test_config()
+            .with_targets(&["answer", "sub/dir/util:static_library"])
+            .compile_args(build_dir);
+        assert_eq!(
+            args,
+            [
+                "compile",
+                "-C",
+                "build",
+                "answer",
+                "sub/dir/util:static_library"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_meson_version_parsed() {
+        let config = test_config();
+        assert_eq!(config.meson_version_parsed(), &Version::new(1, 0, 0));
+        assert_eq!(
+            config.meson_version_parsed().to_string(),
+            config.meson_version()
+        );
+    }
+
+    #[test]
+    fn test_require_version() {
+        let config = test_config();
+
+        config.require_version(">=1.0, <2").unwrap();
+        assert!(matches!(
+            config.require_version("not a version"),
+            Err(Error::InvalidVersionRequirement(_))
+        ));
+
+        let err = config.require_version(">=1.1").unwrap_err();
+        assert!(matches!(
+            err,
+            Error::MesonVersionTooOld { ref found, .. } if *found == Version::new(1, 0, 0)
+        ));
+    }
+
+    #[test]
+    fn test_setup_args_full() {
+        let config = test_config()
+            .with_profile("debugoptimized")
+            .with_backend(Backend::Ninja)
+            .with_wrap_mode(WrapMode::NoDownload)
+            .with_option("default_library", "static")
+            .with_option_bool("tests", false)
+            .with_native_file(Path::new("native.ini"))
+            .with_libdir(Path::new("lib"))
+            .with_setup_arg("--fatal-meson-warnings");
+
+        let out = Path::new("out");
+        let expected: Vec<OsString> = vec![
+            "setup".into(),
+            "--buildtype".into(),
+            "debugoptimized".into(),
+            "--backend".into(),
+            "ninja".into(),
+            "--wrap-mode".into(),
+            "nodownload".into(),
+            "-Ddefault_library=static".into(),
+            "-Dtests=false".into(),
+            "--native-file".into(),
+            "native.ini".into(),
+            "--prefix".into(),
+            env::current_dir().unwrap().join(out).join("install").into(),
+            "--libdir".into(),
+            "lib".into(),
+            "--fatal-meson-warnings".into(),
+            out.join("build").into(),
+            "src".into(),
+        ];
+        assert_eq!(config.setup_args(Path::new("src")).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_layered_machine_files() {
+        let config = test_config()
+            .with_profile("release")
+            .with_native_file(Path::new("base.ini"))
+            .with_added_native_file(Path::new("native.ini"))
+            .with_added_cross_file(Path::new("cross.ini"));
+        let args = config.setup_args(Path::new("src")).unwrap();
+        let machine_files: Vec<_> = args
+            .windows(2)
+            .filter(|pair| pair[0] == "--native-file" || pair[0] == "--cross-file")
+            .map(|pair| (pair[0].clone(), pair[1].clone()))
+            .collect();
+        assert_eq!(
+            machine_files,
+            [
+                ("--native-file".into(), "base.ini".into()),
+                ("--native-file".into(), "native.ini".into()),
+                ("--cross-file".into(), "cross.ini".into()),
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_prefix() {
+        let config = test_config()
+            .with_profile("release")
+            .with_prefix(Path::new("/usr"));
+        let args = config.setup_args(Path::new("src")).unwrap();
+        let prefix = args.iter().position(|arg| arg == "--prefix").unwrap();
+        assert_eq!(args[prefix + 1], "/usr");
+    }
+
+    #[test]
+    fn test_absolute_prefix() {
+        let config = test_config().with_profile("release");
+        assert!(config.install_dir().unwrap().is_relative());
+
+        let args = config.setup_args(Path::new("src")).unwrap();
+        let prefix = args.iter().position(|arg| arg == "--prefix").unwrap();
+        let prefix = Path::new(&args[prefix + 1]);
+        assert!(prefix.is_absolute());
+        assert!(prefix.ends_with(Path::new("out").join("install")));
+    }
+
+    #[test]
+    fn test_new_without_meson() {
+        let config = Config::new("/nonexistent/meson".into(), Version::new(1, 4, 0))
+            .with_out_path(Path::new("out"))
+            .with_profile("debug");
+
+        assert_eq!(config.meson_version(), "1.4.0");
+        let args = config.setup_args(Path::new("src")).unwrap();
+        assert_eq!(args[..3], ["setup", "--buildtype", "debug"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_find_system_meson_cached() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("probes.log");
+        let script = format!("echo probe >> '{}'\necho 1.2.3", log.display());
+        let meson = fake_meson_script_config(dir.path(), &script).meson_path;
+        let probes = || std::fs::read_to_string(&log).unwrap().lines().count();
+
+        with_env(&[("MESON", meson.to_str()), ("TARGET", None)], || {
+            let first = Config::find_system_meson().unwrap();
+            let second = Config::find_system_meson().unwrap();
+            assert_eq!(first.meson_version(), "1.2.3");
+            assert_eq!(second.meson_version(), "1.2.3");
+        });
+        assert_eq!(probes(), 1);
+
+        Config::with_meson_path(&meson).unwrap();
+        assert_eq!(probes(), 2);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_find_first_meson() {
+        let dir = tempfile::tempdir().unwrap();
+        let script = r#"[ "$1 $2 $3" = "-m mesonbuild --version" ] && echo 1.5.0"#;
+        fake_meson_script_config(dir.path(), script);
+        let python = dir.path().join("meson");
+
+        let module = mesonbuild_module();
+        let candidates = vec![
+            (dir.path().join("missing"), Vec::new()),
+            (python.clone(), module.clone()),
+        ];
+        let config = Config::find_first_meson(candidates).unwrap();
+        assert_eq!(config.meson_path, python);
+        assert_eq!(config.meson_args, module);
+        assert_eq!(config.meson_version(), "1.5.0");
+
+        let missing = dir.path().join("missing");
+        let err = Config::find_first_meson(vec![(missing.clone(), Vec::new())]).unwrap_err();
+        assert!(matches!(err, Error::MesonNotFound { tried } if tried == missing));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_with_python_module() {
+        let dir = tempfile::tempdir().unwrap();
+        let script = r#"[ "$1 $2 $3" = "-m mesonbuild --version" ] && echo 1.5.0"#;
+        fake_meson_script_config(dir.path(), script);
+        let python = dir.path().join("meson");
+
+        let config = Config::with_python_module(&python)
+            .unwrap()
+            .with_out_path(Path::new("out"))
+            .with_profile("release");
+        assert_eq!(config.meson_version(), "1.5.0");
+
+        let command = config.setup_command(Path::new("src")).unwrap();
+        assert_eq!(command.get_program(), python);
+        let args: Vec<_> = command.get_args().take(3).collect();
+        assert_eq!(args, ["-m", "mesonbuild", "setup"]);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_meson_candidates_windows() {
+        let candidates = with_env(
+            &[("MESON", None), ("TARGET", None)],
+            Config::meson_candidates,
+        );
+        let programs: Vec<_> = candidates
+            .iter()
+            .map(|(program, _)| program.clone())
+            .collect();
+        assert_eq!(
+            programs,
+            [
+                PathBuf::from("meson"),
+                "meson.exe".into(),
+                "python".into(),
+                "py".into()
+            ]
+        );
+        assert_eq!(candidates[2].1, ["-m", "mesonbuild"]);
+
+        let candidates = with_env(
+            &[("MESON", Some("C:\\meson\\meson.exe")), ("TARGET", None)],
+            || Config::meson_candidates(),
+        );
+        assert_eq!(
+            candidates,
+            [(PathBuf::from("C:\\meson\\meson.exe"), Vec::new())]
+        );
+    }
+
+    #[test]
+    fn test_with_meson_path_bogus() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let meson = dir.path().join("meson");
+        let err = Config::with_meson_path(&meson).unwrap_err();
+        assert!(matches!(err, Error::MesonNotFound { ref tried } if *tried == meson));
+
+        let err = with_env(
+            &[("MESON", Some("meson-ext-rs-missing")), ("TARGET", None)],
+            || Config::find_system_meson().unwrap_err(),
+        );
+        assert!(
+            matches!(err, Error::MesonNotFound { ref tried } if *tried == Path::new("meson-ext-rs-missing"))
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_with_meson_path_not_executable() {
+        let dir = tempfile::tempdir().unwrap();
+        let meson = dir.path().join("meson");
+        std::fs::write(&meson, "").unwrap();
+
+        let err = Config::with_meson_path(&meson).unwrap_err();
+        assert!(matches!(err, Error::MesonNotExecutable(path) if path == meson));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_reconfigure() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = fake_meson_config(dir.path());
+
+        std::fs::create_dir_all(config.build_dir().unwrap()).unwrap();
+        std::fs::write(config.build_dir().unwrap().join("build.ninja"), "").unwrap();
+        std::fs::write(
+            config.configuration_state_path().unwrap(),
+            config.configuration_state(),
+        )
+        .unwrap();
+
+        config.configure(dir.path()).unwrap();
+        assert!(fake_meson_log(dir.path()).is_empty());
+
+        config.set_reconfigure(true);
+        let args = config.setup_args(dir.path()).unwrap();
+        assert!(args.iter().any(|arg| arg == "--reconfigure"));
+
+        config.configure(dir.path()).unwrap();
+        let log = fake_meson_log(dir.path());
+        assert_eq!(log.len(), 1);
+        assert!(log[0].starts_with("setup --reconfigure"));
+    }
+
+    #[test]
+    fn test_wipe_arg() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = test_config()
+            .with_out_path(dir.path())
+            .with_profile("release");
+
+        config.set_wipe(true);
+        let args = config.setup_args(dir.path()).unwrap();
+        assert!(!args.iter().any(|arg| arg == "--wipe"));
+
+        std::fs::create_dir_all(config.build_dir().unwrap()).unwrap();
+        std::fs::write(config.build_dir().unwrap().join("build.ninja"), "").unwrap();
+        let args = config.setup_args(dir.path()).unwrap();
+        assert!(args.iter().any(|arg| arg == "--wipe"));
+
+        config.set_wipe(false);
+        let args = config.setup_args(dir.path()).unwrap();
+        assert!(!args.iter().any(|arg| arg == "--wipe"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_wipe_on_meson_upgrade() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = fake_meson_config(dir.path());
+        let build_dir = config.build_dir().unwrap();
+
+        std::fs::create_dir_all(&build_dir).unwrap();
+        std::fs::write(build_dir.join("build.ninja"), "").unwrap();
+        config.save_configuration_state().unwrap();
+        config.configure(dir.path()).unwrap();
+        assert!(fake_meson_log(dir.path()).is_empty());
+
+        std::fs::write(config.meson_version_marker_path().unwrap(), "0.63.0\n").unwrap();
+        assert!(config.configure(dir.path()).unwrap());
+        let log = fake_meson_log(dir.path());
+        assert!(log[0].starts_with("setup --wipe"));
+        assert_eq!(
+            std::fs::read_to_string(config.meson_version_marker_path().unwrap()).unwrap(),
+            "1.0.0\n"
+        );
+    }
+
+    #[test]
+    fn test_clean() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = test_config().with_out_path(dir.path());
+
+        std::fs::create_dir_all(config.build_dir().unwrap()).unwrap();
+        std::fs::write(config.build_dir().unwrap().join("build.ninja"), "").unwrap();
+
+        config.clean().unwrap();
+        assert!(!config.build_dir().unwrap().exists());
+        assert!(dir.path().exists());
+
+        // Nothing to clean
+        config.clean().unwrap();
+    }
+
+    #[test]
+    fn test_json_string() {
+        assert_eq!(json_string("plain"), r#""plain""#);
+        assert_eq!(json_string(r#"a"b\c"#), r#""a\"b\\c""#);
+        assert_eq!(json_string("a\nb"), r#""a\u000ab""#);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_reconfigure_on_changed_options() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = fake_meson_config(dir.path()).with_option("foo", "bar");
+
+        config.configure(dir.path()).unwrap();
+        // The fake meson does not generate anything
+        std::fs::write(config.build_dir().unwrap().join("build.ninja"), "").unwrap();
+
+        config.configure(dir.path()).unwrap();
+        let log = fake_meson_log(dir.path());
+        assert_eq!(log.len(), 1);
+        assert!(!log[0].contains("--reconfigure"));
+
+        config.set_option("foo", "baz");
+        config.configure(dir.path()).unwrap();
+        let log = fake_meson_log(dir.path());
+        assert_eq!(log.len(), 2);
+        assert!(log[1].starts_with("setup --reconfigure"));
+        assert!(log[1].contains("-Dfoo=baz"));
+
+        config.configure(dir.path()).unwrap();
+        assert_eq!(fake_meson_log(dir.path()).len(), 2);
+    }
+
+    #[test]
+    fn test_test_args() {
+        let build_dir = Path::new("build");
+        let config = test_config();
+
+        assert_eq!(
+            config.test_args(build_dir, None, &[]),
+            ["test", "-C", "build"]
+        );
+        assert_eq!(
+            config.test_args(build_dir, Some("unit"), &["first", "second"]),
+            ["test", "-C", "build", "--suite", "unit", "first", "second"]
+        );
+    }
+
+    #[test]
+    fn test_buildtype_from_cargo() {
+        assert_eq!(buildtype_from_cargo("0", true), "debug");
+        assert_eq!(buildtype_from_cargo("0", false), "plain");
+        for opt_level in ["1", "2", "3"] {
+            assert_eq!(buildtype_from_cargo(opt_level, true), "debugoptimized");
+            assert_eq!(buildtype_from_cargo(opt_level, false), "release");
+        }
+        for opt_level in ["s", "z"] {
+            assert_eq!(buildtype_from_cargo(opt_level, true), "minsize");
+            assert_eq!(buildtype_from_cargo(opt_level, false), "minsize");
+        }
+    }
+
+    #[test]
+    fn test_set_buildtype_from_cargo() {
+        let mut config = with_vars(test_config(), &[("OPT_LEVEL", "3"), ("DEBUG", "false")]);
+        config.set_buildtype_from_cargo();
+        assert_eq!(config.profile(), "release");
+
+        let mut config = with_vars(config, &[("OPT_LEVEL", "2"), ("DEBUG", "true")]);
+        config.set_buildtype_from_cargo();
+        assert_eq!(config.profile(), "debugoptimized");
+
+        let mut config = test_config().with_profile("custom");
+        config.set_buildtype_from_cargo();
+        assert_eq!(config.profile(), "custom");
+    }
+
+    #[test]
+    fn test_buildtype_validation() {
+        let config = test_config().with_profile("minsize");
+        let args = config.setup_args(Path::new("src")).unwrap();
+        assert!(args
+            .windows(2)
+            .any(|pair| pair[0] == "--buildtype" && pair[1] == "minsize"));
+
+        let config = test_config().with_profile("fastest");
+        assert!(matches!(
+            config.setup_args(Path::new("src")),
+            Err(Error::InvalidBuildtype(buildtype)) if buildtype == "fastest"
+        ));
+    }
+
+    #[test]
+    fn test_option_default() {
+        let config = test_config()
+            .with_profile("release")
+            .with_option("docs", "true")
+            .with_option_default("docs", "false")
+            .with_option_default("tests", "false");
+        assert_eq!(option_args(&config), ["-Ddocs=true", "-Dtests=false"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_quiet() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = fake_meson_config(dir.path()).with_profile("");
+        config.profile = None;
+
+        let build = |config: Config| config.build(dir.path()).unwrap();
+        let printed = printed_by(config.clone(), build);
+        assert_eq!(
+            printed,
+            ["cargo:info=PROFILE is not set, using release as default."]
+        );
+
+        let config = config.with_profile("");
+        let printed = printed_by(config.clone(), build);
+        assert_eq!(
+            printed,
+            ["cargo:info=profile is empty, ignoring profile option."]
+        );
+
+        let printed = printed_by(config.with_quiet(true), build);
+        assert!(printed.is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_unknown_profile_warned_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = with_vars(fake_meson_config(dir.path()), &[("PROFILE", "bench")]);
+        config.profile = None;
+        assert_eq!(config.profile(), "release");
+
+        let printed = printed_by(config, |config| config.build(dir.path()).unwrap());
+        assert_eq!(printed.len(), 1);
+        assert!(printed[0].starts_with("cargo:warning=PROFILE 'bench' is unknown."));
+    }
+
+    #[test]
+    fn test_typed_options() {
+        let config = test_config()
+            .with_profile("release")
+            .with_option_bool("docs", false)
+            .with_option_bool("tests", true)
+            .with_option_feature("ssl", Feature::Enabled)
+            .with_option_feature("tls", Feature::Disabled)
+            .with_option_feature("zlib", Feature::Auto);
+
+        assert_eq!(
+            option_args(&config),
+            [
+                "-Ddocs=false",
+                "-Dssl=enabled",
+                "-Dtests=true",
+                "-Dtls=disabled",
+                "-Dzlib=auto",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_split_args() {
+        assert_eq!(split_args(""), Vec::<String>::new());
+        assert_eq!(
+            split_args("  --vsenv   -Dfoo=bar "),
+            ["--vsenv", "-Dfoo=bar"]
+        );
+        assert_eq!(
+            split_args(r#"-Dc_args='-DA -DB' "-Dname=a \"b\"" a\ b '' x"y"z"#),
+            ["-Dc_args=-DA -DB", r#"-Dname=a "b""#, "a b", "", "xyz"]
+        );
+    }
+
+    #[test]
+    fn test_setup_args_from_env() {
+        let target = ("TARGET", "x86_64-unknown-linux-gnu");
+        let specific = "MESON_ARGS_X86_64_UNKNOWN_LINUX_GNU";
+
+        let config = with_vars(test_config(), &[target, ("MESON_ARGS", "--vsenv")]);
+        let args = config.setup_args(Path::new("src")).unwrap();
+        assert!(args.contains(&OsString::from("--vsenv")));
+
+        let vars = [
+            target,
+            ("MESON_ARGS", "--vsenv"),
+            (specific, "'-Dname=target value'"),
+        ];
+        let args = with_vars(test_config(), &vars)
+            .setup_args(Path::new("src"))
+            .unwrap();
+        assert!(args.contains(&OsString::from("-Dname=target value")));
+        assert!(!args.contains(&OsString::from("--vsenv")));
+    }
+
+    #[test]
+    fn test_cmake_prefix_path() {
+        let config = test_config()
+            .with_profile("release")
+            .with_cmake_prefix_path(Path::new("/opt/fmt"))
+            .with_cmake_prefix_path(Path::new("/opt/my deps"));
+
+        assert_eq!(
+            option_args(&config),
+            ["-Dcmake_prefix_path=['/opt/fmt','/opt/my deps']"]
+        );
+    }
+
+    #[test]
+    fn test_remove_and_clear_options() {
+        let mut config = test_config()
+            .with_profile("release")
+            .with_option("default_library", "static")
+            .with_option("docs", "false")
+            .with_option("tests", "true");
+
+        assert_eq!(config.remove_option("docs"), Some("false".to_string()));
+        assert_eq!(config.remove_option("docs"), None);
+        assert_eq!(
+            option_args(&config),
+            ["-Ddefault_library=static", "-Dtests=true"]
+        );
+
+        config.clear_options();
+        assert!(option_args(&config).is_empty());
+    }
+
+    #[test]
+    fn test_options_iter() {
+        let config = test_config()
+            .with_option("tests", "true")
+            .with_option_feature("ssl", Feature::Enabled);
+
+        let options: Vec<_> = config.options().collect();
+        assert_eq!(options, [("ssl", "enabled"), ("tests", "true")]);
+
+        assert_eq!(config.get_option("ssl"), Some("enabled"));
+        assert_eq!(config.get_option("docs"), None);
+    }
+
+    #[test]
+    fn test_sanitizer_option() {
+        let config = test_config()
+            .with_profile("debug")
+            .with_sanitizer(Sanitizer::AddressUndefined);
+        assert_eq!(option_args(&config), ["-Db_sanitize=address,undefined"]);
+
+        let config = config.with_sanitizer(Sanitizer::Thread);
+        assert_eq!(option_args(&config), ["-Db_sanitize=thread"]);
+    }
+
+    #[test]
+    fn test_lto_options() {
+        let config = test_config().with_profile("release").with_lto(true);
+        assert_eq!(option_args(&config), ["-Db_lto=true"]);
+
+        let config = config.with_lto_mode(LtoMode::Thin);
+        assert_eq!(option_args(&config), ["-Db_lto=true", "-Db_lto_mode=thin"]);
+
+        let config = config.with_lto(false).with_lto_mode(LtoMode::Default);
+        assert_eq!(
+            option_args(&config),
+            ["-Db_lto=false", "-Db_lto_mode=default"]
+        );
+    }
+
+    #[test]
+    fn test_coverage() {
+        let config = test_config().with_profile("debug").with_coverage(true);
+        assert_eq!(option_args(&config), ["-Db_coverage=true"]);
+
+        let args = config.coverage_args(Path::new("build"));
+        assert_eq!(args, ["compile", "-C", "build", "coverage"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_dist() {
+        let dir = tempfile::tempdir().unwrap();
+        let dist_dir = dir.path().join("build").join("meson-dist");
+        let log = dir.path().join("meson.log");
+        let script = format!(
+            "echo \"$@\" >> '{log}'\nmkdir -p '{dist}'\n\
+            touch '{dist}/fake-1.0.tar.xz' '{dist}/fake-1.0.tar.xz.sha256sum' '{dist}/fake-1.0.zip'",
+            log = log.display(),
+            dist = dist_dir.display()
+        );
+        let config = fake_meson_script_config(dir.path(), &script);
+        let build_dir = config.build_dir().unwrap();
+
+        assert_eq!(config.dist().unwrap(), dist_dir.join("fake-1.0.tar.xz"));
+        assert_eq!(
+            config.dist_with_formats(&["zip", "xztar"]).unwrap(),
+            [
+                dist_dir.join("fake-1.0.zip"),
+                dist_dir.join("fake-1.0.tar.xz")
+            ]
+        );
+        assert_eq!(
+            fake_meson_log(dir.path()),
+            [
+                format!("dist -C {} --formats xztar", build_dir.display()),
+                format!("dist -C {} --formats zip,xztar", build_dir.display()),
+            ]
+        );
+
+        let err = config.dist_with_formats(&["gztar"]).unwrap_err();
+        assert!(matches!(err, Error::DistArchiveNotFound { ref format, .. } if format == "gztar"));
+    }
+
+    #[test]
+    fn test_rewrite_args() {
+        let args = test_config().rewrite_args(
+            Path::new("src"),
+            &["kwargs", "set", "project", "/", "version", "1.2.0"],
+        );
+        assert_eq!(
+            args,
+            [
+                "rewrite",
+                "--sourcedir",
+                "src",
+                "kwargs",
+                "set",
+                "project",
+                "/",
+                "version",
+                "1.2.0"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_pkgconfig_relocatable() {
+        let config = test_config()
+            .with_profile("release")
+            .with_pkgconfig_relocatable(true);
+        assert_eq!(option_args(&config), ["-Dpkgconfig.relocatable=true"]);
+    }
+
+    #[test]
+    fn test_optimization_and_debug() {
+        let config = test_config()
+            .with_profile("release")
+            .with_optimization("2")
+            .with_debug(true);
+        let args = config.setup_args(Path::new("src")).unwrap();
+        assert!(args.windows(2).any(|w| w == ["--buildtype", "release"]));
+        assert_eq!(option_args(&config), ["-Ddebug=true", "-Doptimization=2"]);
+    }
+
+    #[test]
+    fn test_default_library() {
+        let config = test_config()
+            .with_profile("release")
+            .with_default_library(LibraryKind::Static);
+        assert_eq!(option_args(&config), ["-Ddefault_library=static"]);
+    }
+
+    #[test]
+    fn test_warnings() {
+        let config = test_config()
+            .with_profile("release")
+            .with_warning_level(1)
+            .unwrap()
+            .with_werror(true);
+        assert_eq!(option_args(&config), ["-Dwarning_level=1", "-Dwerror=true"]);
+
+        let mut config = config.with_warning_level(4).unwrap();
+        assert_eq!(
+            option_args(&config),
+            ["-Dwarning_level=everything", "-Dwerror=true"]
+        );
+
+        let err = config.set_warning_level(200).unwrap_err();
+        assert!(matches!(err, Error::InvalidWarningLevel(200)));
+        assert_eq!(
+            option_args(&config),
+            ["-Dwarning_level=everything", "-Dwerror=true"]
+        );
+    }
+
+    #[test]
+    fn test_unity() {
+        let config = test_config()
+            .with_profile("release")
+            .with_unity(UnityMode::Subprojects)
+            .with_unity_size(8);
+        assert_eq!(
+            option_args(&config),
+            ["-Dunity=subprojects", "-Dunity_size=8"]
+        );
+
+        let config = config.with_unity(UnityMode::On);
+        assert_eq!(option_args(&config), ["-Dunity=on", "-Dunity_size=8"]);
+    }
+
+    #[test]
+    fn test_array_options() {
+        let config = test_config()
+            .with_profile("release")
+            .with_option_array("c_args", &["-DFOO", "-DBAR='a, b'"])
+            .with_option_array("empty", &[]);
+
+        assert_eq!(
+            option_args(&config),
+            [r"-Dc_args=['-DFOO','-DBAR=\'a, b\'']", "-Dempty=[]"]
+        );
+    }
+
+    #[test]
+    fn test_compiler_args() {
+        let mut config = test_config().with_profile("release");
+        assert!(option_args(&config).is_empty());
+
+        config.add_c_arg("-DFOO");
+        config.add_c_arg("-Iinclude dir");
+        config.add_cpp_arg("-std=c++17");
+        config.add_link_arg("-lm");
+        config.set_option("c_args", "ignored");
+
+        assert_eq!(
+            option_args(&config),
+            [
+                "-Dc_args=['-DFOO','-Iinclude dir']",
+                "-Dc_link_args=['-lm']",
+                "-Dcpp_args=['-std=c++17']",
+                "-Dcpp_link_args=['-lm']",
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_configure_failure_stderr() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = fake_meson_script_config(
+            dir.path(),
+            "echo 'The Meson build system'\n\
+             echo 'ERROR: Neither directory contains a build file meson.build.' >&2\n\
+             exit 1",
+        );
+
+        let err = config.configure(dir.path()).unwrap_err();
+        match err {
+            Error::MesonConfiguredUnsuccessfully {
+                code,
+                ref stderr,
+                ref log_path,
+            } => {
+                assert_eq!(code, 1);
+                assert_eq!(*log_path, None);
+                assert_eq!(
+                    stderr,
+                    "ERROR: Neither directory contains a build file meson.build.\n"
+                );
+            }
+            _ => panic!("unexpected error: {err}"),
+        }
+        assert!(err.to_string().contains("Neither directory contains"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_configure_failure_log_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let log_path = dir.path().join("build/meson-logs/meson-log.txt");
+        let script = format!(
+            "mkdir -p '{}'\necho details > '{}'\nexit 1",
+            log_path.parent().unwrap().display(),
+            log_path.display()
+        );
+        let config = fake_meson_script_config(dir.path(), &script);
+
+        let err = config.configure(dir.path()).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::MesonConfiguredUnsuccessfully { log_path: Some(ref path), .. } if *path == log_path
+        ));
+        assert!(err
+            .to_string()
+            .ends_with(&format!("see {} for details", log_path.display())));
+    }
+
+    #[test]
+    fn test_rerun_if_changed() {
+        let source = tempfile::tempdir().unwrap();
+        std::fs::write(source.path().join("meson.build"), "").unwrap();
+
+        let lines = with_vars(test_config(), &[("TARGET", "x86_64-unknown-linux-gnu")])
+            .rerun_if_changed(source.path(), false)
+            .unwrap();
+
+        assert_eq!(
+            lines,
+            [
+                format!(
+                    "cargo:rerun-if-changed={}",
+                    source.path().join("meson.build").display()
+                ),
+                "cargo:rerun-if-env-changed=MESON".to_string(),
+                "cargo:rerun-if-env-changed=MESON_X86_64_UNKNOWN_LINUX_GNU".to_string(),
+                "cargo:rerun-if-env-changed=MESON_ARGS".to_string(),
+                "cargo:rerun-if-env-changed=MESON_ARGS_X86_64_UNKNOWN_LINUX_GNU".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_dir_names() {
+        let config = test_config();
+        assert_eq!(config.build_dir().unwrap(), Path::new("out").join("build"));
+        assert_eq!(
+            config.install_dir().unwrap(),
+            Path::new("out").join("install")
+        );
+
+        let config = test_config()
+            .with_build_dir_name("zlib-build")
+            .with_install_dir_name("zlib-install");
+        assert_eq!(
+            config.build_dir().unwrap(),
+            Path::new("out").join("zlib-build")
+        );
+        assert_eq!(
+            config.install_dir().unwrap(),
+            Path::new("out").join("zlib-install")
+        );
+    }
+
+    #[test]
+    fn test_no_meson_build_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = test_config().with_out_path(dir.path());
+
+        let err = config.configure(dir.path()).unwrap_err();
+        assert!(matches!(err, Error::NoMesonBuildFile(path) if path == dir.path()));
+    }
+
+    #[test]
+    fn test_out_dir_not_set() {
+        let mut config = test_config().with_profile("release");
+        config.out_path = None;
+
+        let results = [config.out_path(), config.build_dir(), config.install_dir()];
+        let setup = config.setup_args(Path::new("src"));
+
+        for result in results {
+            assert!(matches!(result, Err(Error::OutDirNotSet)));
+        }
+        assert!(matches!(setup, Err(Error::OutDirNotSet)));
+    }
+
+    #[test]
+    fn test_profile_without_env() {
+        assert_eq!(test_config().profile(), "release");
+
+        let config = with_vars(test_config(), &[("PROFILE", "debug")]);
+        assert_eq!(config.profile(), "debug");
+    }
+
+    #[test]
+    fn test_generate_native_file_from_env() {
+        let dir = tempfile::tempdir().unwrap();
+        let vars = [("CC", "clang"), ("CXX", "clang++")];
+        let mut config = with_vars(test_config(), &vars).with_out_path(dir.path());
+
+        config.generate_native_file_from_env().unwrap();
+
+        let native_file = dir.path().join("meson-native.ini");
+        assert_eq!(config.native_files, vec![native_file.clone()]);
+        assert_eq!(
+            std::fs::read_to_string(native_file).unwrap(),
+            "[binaries]\nc = ['clang']\ncpp = ['clang++']\n"
+        );
+    }
+
+    #[test]
+    fn test_generate_cross_file_for_target() {
+        let dir = tempfile::tempdir().unwrap();
+        let vars = [
+            ("TARGET", "aarch64-unknown-linux-gnu"),
+            ("CC_aarch64_unknown_linux_gnu", "aarch64-linux-gnu-gcc"),
+        ];
+        let mut config = with_vars(test_config(), &vars).with_out_path(dir.path());
+
+        config.generate_cross_file_for_target().unwrap();
+
+        let cross_file = dir.path().join("meson-cross.ini");
+        assert_eq!(config.cross_files, vec![cross_file.clone()]);
+        assert_eq!(
+            std::fs::read_to_string(cross_file).unwrap(),
+            "[binaries]\n\
+             c = ['aarch64-linux-gnu-gcc']\n\
+             \n\
+             [host_machine]\n\
+             system = 'linux'\n\
+             cpu_family = 'aarch64'\n\
+             cpu = 'aarch64'\n\
+             endian = 'little'\n"
+        );
+
+        let mut config = with_vars(config, &[("TARGET", "bogus-unknown-unknown")]);
+        let err = config.generate_cross_file_for_target().unwrap_err();
+        assert!(matches!(err, Error::UnsupportedTarget(_)));
+
+        // The OS of this triple is unknown to the triple parsing
+        let cfg = [
+            ("TARGET", "x86_64-unknown-hermit"),
+            ("CARGO_CFG_TARGET_ARCH", "x86_64"),
+            ("CARGO_CFG_TARGET_OS", "hermit"),
+            ("CARGO_CFG_TARGET_ENDIAN", "little"),
+            ("CARGO_CFG_TARGET_POINTER_WIDTH", "64"),
+        ];
+        let mut config = with_vars(config, &cfg);
+        config.generate_cross_file_for_target().unwrap();
+        let contents = std::fs::read_to_string(dir.path().join("meson-cross.ini")).unwrap();
+        assert!(contents.ends_with(
+            "[host_machine]\n\
+             system = 'hermit'\n\
+             cpu_family = 'x86_64'\n\
+             cpu = 'x86_64'\n\
+             endian = 'little'\n"
+        ));
+    }
+
+    #[test]
+    fn test_destdir_env() {
+        let destdir = |config: &Config| {
+            let command = config.install_command(Path::new("build")).unwrap();
+            command
+                .get_envs()
+                .find(|(key, _)| *key == "DESTDIR")
+                .and_then(|(_, value)| value.map(PathBuf::from))
+        };
+
+        assert_eq!(destdir(&test_config()), None);
+
+        let config = test_config().with_destdir(Path::new("staging"));
+        assert_eq!(destdir(&config), Some("staging".into()));
+    }
+
+    #[test]
+    fn test_program_from_env() {
+        let target = "x86_64-unknown-linux-gnu";
+        for (var, default) in [("MESON", "meson"), ("NINJA", "ninja")] {
+            let specific = format!("{var}_X86_64_UNKNOWN_LINUX_GNU");
+
+            let program = with_env(
+                &[("TARGET", Some(target)), (var, None), (&specific, None)],
+                || program_from_env(var, default),
+            );
+            assert_eq!(program, Path::new(default));
+
+            let program = with_env(
+                &[
+                    ("TARGET", Some(target)),
+                    (var, Some("generic")),
+                    (&specific, None),
+                ],
+                || program_from_env(var, default),
+            );
+            assert_eq!(program, Path::new("generic"));
+
+            let program = with_env(
+                &[
+                    ("TARGET", Some(target)),
+                    (var, Some("generic")),
+                    (&specific, Some("specific")),
+                ],
+                || program_from_env(var, default),
+            );
+            assert_eq!(program, Path::new("specific"));
+        }
+    }
+
+    #[test]
+    fn test_ninja_path_exported() {
+        let config = test_config().with_ninja_path(Path::new("/opt/ninja/bin/ninja"));
+        let command = config.meson_command();
+        let ninja = command.get_envs().find(|(key, _)| *key == "NINJA");
+        assert_eq!(
+            ninja,
+            Some((
+                OsStr::new("NINJA"),
+                Some(OsStr::new("/opt/ninja/bin/ninja"))
+            ))
+        );
+    }
+
+    #[test]
+    fn test_subprojects_dir_exported() {
+        let config = test_config().with_subprojects_dir(Path::new("/opt/wraps"));
+        let command = config.meson_command();
+        let dir = command
+            .get_envs()
+            .find(|(key, _)| *key == "MESON_PACKAGE_CACHE_DIR");
+        assert_eq!(
+            dir,
+            Some((
+                OsStr::new("MESON_PACKAGE_CACHE_DIR"),
+                Some(OsStr::new("/opt/wraps"))
+            ))
+        );
+    }
+
+    #[test]
+    fn test_env_overrides() {
+        let config = test_config()
+            .with_profile("release")
+            .with_env("PKG_CONFIG_PATH", "/opt/lib/pkgconfig")
+            .without_env("CFLAGS");
+
+        let command = config.setup_command(Path::new("src")).unwrap();
+        let envs: Vec<_> = command.get_envs().collect();
+        assert_eq!(
+            envs,
+            [
+                (OsStr::new("CFLAGS"), None),
+                (
+                    OsStr::new("PKG_CONFIG_PATH"),
+                    Some(OsStr::new("/opt/lib/pkgconfig"))
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_compiler_cache() {
+        let compilers = |config: &Config| {
+            let command = config.setup_command(Path::new("src")).unwrap();
+            let compilers: Vec<_> = command
+                .get_envs()
+                .filter(|(key, _)| *key == "CC" || *key == "CXX")
+                .map(|(_, value)| value.unwrap().to_str().unwrap().to_string())
+                .collect();
+            compilers
+        };
+
+        let config = test_config()
+            .with_profile("release")
+            .with_env("CC", "clang");
+        assert_eq!(compilers(&config), ["clang"]);
+
+        let config = config
+            .with_env("CXX", "clang++")
+            .with_compiler_cache(CompilerCache::Custom("buildcache".to_string()));
+        assert_eq!(
+            compilers(&config),
+            ["buildcache clang", "buildcache clang++"]
+        );
+    }
+
+    #[test]
+    fn test_skipped_subprojects() {
+        let subprojects = [
+            "zlib".to_string(),
+            "libpng".to_string(),
+            "gtest".to_string(),
+        ];
+        assert_eq!(test_config().skipped_subprojects(&subprojects), None);
+
+        let config = test_config().with_subproject_filter(&["zlib"]);
+        assert_eq!(
+            config.skipped_subprojects(&subprojects),
+            Some("libpng,gtest".to_string())
+        );
+
+        let config = test_config().with_subproject_filter(&["zlib", "libpng", "gtest"]);
+        assert_eq!(config.skipped_subprojects(&subprojects), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_subproject_filter_install_args() {
+        let dir = tempfile::tempdir().unwrap();
+        let script = r#"[ "$1" = introspect ] && echo '{"subprojects": [{"name": "zlib"}, {"name": "gtest"}]}'
+exit 0"#;
+        let config = fake_meson_script_config(dir.path(), script).with_subproject_filter(&["zlib"]);
+
+        let command = config.install_command(Path::new("build")).unwrap();
+        let args: Vec<_> = command.get_args().collect();
+        assert_eq!(
+            args,
+            ["install", "-C", "build", "--skip-subprojects", "gtest"]
+        );
+    }
+
+    #[test]
+    fn test_install_tags() {
+        let args = |config: &Config| {
+            let command = config.install_command(Path::new("build")).unwrap();
+            let args: Vec<_> = command.get_args().map(OsStr::to_os_string).collect();
+            args
+        };
+
+        assert_eq!(args(&test_config()), ["install", "-C", "build"]);
+        assert_eq!(
+            args(&test_config().with_install_tags(&["devel", "runtime"])),
+            ["install", "-C", "build", "--tags", "devel,runtime"]
+        );
+    }
+
+    #[test]
+    fn test_strip_on_install() {
+        let strips = |config: &Config| {
+            let command = config.install_command(Path::new("build")).unwrap();
+            let mut args = command.get_args();
+            args.any(|arg| arg == "--strip")
+        };
+
+        assert!(!strips(&test_config()));
+
+        let config = test_config().with_strip(true);
+        assert!(strips(&config));
+        assert!(!config
+            .compile_args(Path::new("build"))
+            .iter()
+            .any(|arg| arg == "--strip"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_build_without_install() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let config = fake_meson_config(dir.path());
+        config.build(dir.path()).unwrap();
+        let log = fake_meson_log(dir.path());
+        assert!(log.last().unwrap().starts_with("install"));
+
+        std::fs::remove_file(dir.path().join("meson.log")).unwrap();
+
+        let config = fake_meson_config(dir.path()).with_install(false);
+        config.build(dir.path()).unwrap();
+        let log = fake_meson_log(dir.path());
+        assert!(!log.is_empty());
+        assert!(!log.iter().any(|line| line.starts_with("install")));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_paths_with_spaces_and_unicode() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("meson.log");
+        let script = format!(
+            "for arg in \"$@\"; do echo \"$arg\"; done >> '{}'",
+            log.display()
+        );
+
+        let out_dir = dir.path().join("out dir");
+        let source_dir = dir.path().join("sources").join("ünïcødé project");
+        std::fs::create_dir_all(&source_dir).unwrap();
+        std::fs::write(source_dir.join("meson.build"), "project('fake')\n").unwrap();
+
+        let config = fake_meson_script_config(dir.path(), &script)
+            .with_out_path(&out_dir)
+            .with_native_file(&dir.path().join("native file.ini"));
+        config.build(&source_dir).unwrap();
+
+        let log = std::fs::read_to_string(log).unwrap();
+        let args: Vec<&str> = log.lines().collect();
+        let setup_end = args.iter().position(|arg| *arg == "compile").unwrap();
+        let after = |flag: &str| {
+            let position = args[..setup_end].iter().position(|arg| *arg == flag)?;
+            Some(Path::new(args[position + 1]))
+        };
+
+        assert_eq!(after("--prefix"), Some(out_dir.join("install").as_path()));
+        assert_eq!(
+            after("--native-file"),
+            Some(dir.path().join("native file.ini").as_path())
+        );
+        assert_eq!(
+            args[setup_end - 2..setup_end],
+            [
+                out_dir.join("build").to_str().unwrap(),
+                source_dir.to_str().unwrap()
+            ]
+        );
+        assert_eq!(args[setup_end + 2], out_dir.join("build").to_str().unwrap());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_build_with_outcome() {
+        let dir = tempfile::tempdir().unwrap();
+        let script = r#"if [ "$1" = install ]; then
+    mkdir -p "$3/meson-logs"
+    printf '# List of files installed by Meson\n/prefix/lib/libanswer.a\n' > "$3/meson-logs/install-log.txt"
+fi"#;
+        let config = fake_meson_script_config(dir.path(), script);
+
+        let outcome = config.clone().build_with_outcome(dir.path()).unwrap();
+        assert_eq!(
+            outcome,
+            BuildOutcome {
+                build_dir: dir.path().join("build"),
+                install_dir: dir.path().join("install"),
+                configured: true,
+                installed_files: vec!["/prefix/lib/libanswer.a".into()],
+            }
+        );
+
+        let outcome = config
+            .with_install(false)
+            .build_with_outcome(dir.path())
+            .unwrap();
+        assert!(outcome.installed_files.is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_build_with_outcome_dry_run() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = fake_meson_config(dir.path()).with_dry_run(true);
+
+        let outcome = config.build_with_outcome(dir.path()).unwrap();
+        assert!(outcome.installed_files.is_empty());
+        assert!(!dir.path().join("meson.log").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_output_callback() {
+        let dir = tempfile::tempdir().unwrap();
+        let lines = Arc::new(Mutex::new(Vec::new()));
+        let collected = lines.clone();
+
+        let script = r#"echo "running $1"; echo "warning from $1" >&2"#;
+        let config = fake_meson_script_config(dir.path(), script)
+            .with_install(false)
+            .with_output_callback(move |line| collected.lock().unwrap().push(line.to_string()));
+        config.build(dir.path()).unwrap();
+
+        // The stdout and stderr of a command are read concurrently
+        let mut lines = lines.lock().unwrap().clone();
+        lines[..2].sort();
+        lines[2..].sort();
+        assert_eq!(
+            lines,
+            [
+                "running setup",
+                "warning from setup",
+                "running compile",
+                "warning from compile",
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_progress_callback() {
+        let dir = tempfile::tempdir().unwrap();
+        let progress = Arc::new(Mutex::new(Vec::new()));
+        let collected = progress.clone();
+
+        let script =
+            r#"[ "$1" = compile ] && printf '[1/2] Compiling C object\n[2/2] Linking\n'; exit 0"#;
+        let config = fake_meson_script_config(dir.path(), script)
+            .with_install(false)
+            .with_output_callback(|_| {})
+            .with_progress_callback(move |done, total| {
+                collected.lock().unwrap().push((done, total))
+            });
+        config.build(dir.path()).unwrap();
+
+        assert_eq!(*progress.lock().unwrap(), [(1, 2), (2, 2)]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_tests_json() {
+        let dir = tempfile::tempdir().unwrap();
+        let script = r#"mkdir -p "$3/meson-logs"
+echo '{"name": "broken", "result": "FAIL", "duration": 0.5}' > "$3/meson-logs/testlog.json"
+exit 1"#;
+        let config = fake_meson_script_config(dir.path(), script);
+
+        let results = config.run_tests_json().unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].name, "broken");
+        assert!(!results[0].passed());
+
+        // The log of the previous run is not reported as the result of a dry run
+        let results = config.with_dry_run(true).run_tests_json().unwrap();
+        assert!(results.is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_build_incremental() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = fake_meson_config(dir.path());
+
+        let err = config.build_incremental(dir.path()).unwrap_err();
+        assert!(matches!(err, Error::NotConfigured(ref path) if *path == dir.path().join("build")));
+        assert!(!dir.path().join("meson.log").exists());
+
+        std::fs::create_dir_all(dir.path().join("build")).unwrap();
+        std::fs::write(dir.path().join("build").join("build.ninja"), "").unwrap();
+        config.build_incremental(dir.path()).unwrap();
+
+        let log = fake_meson_log(dir.path());
+        assert!(log[0].starts_with("compile"));
+        assert!(!log.iter().any(|line| line.starts_with("setup")));
+    }
+
+    #[test]
+    fn test_is_configured() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = test_config().with_out_path(dir.path());
+        assert!(!config.is_configured());
+
+        let build_dir = config.build_dir().unwrap();
+        std::fs::create_dir_all(&build_dir).unwrap();
+        std::fs::write(build_dir.join("build.ninja"), "").unwrap();
+        assert!(config.is_configured());
+    }
+
+    #[test]
+    fn test_is_configured_per_backend() {
+        let sentinels = [
+            (Backend::Ninja, "build.ninja"),
+            (Backend::Vs2022, "answer.sln"),
+            (Backend::Xcode, "answer.xcodeproj"),
+            (Backend::None, "meson-private/coredata.dat"),
+        ];
+
+        for (backend, sentinel) in sentinels {
+            let dir = tempfile::tempdir().unwrap();
+            let config = test_config()
+                .with_out_path(dir.path())
+                .with_backend(backend);
+            let build_dir = config.build_dir().unwrap();
+            std::fs::create_dir_all(build_dir.join("meson-private")).unwrap();
+            assert!(!config.is_configured(), "{backend}");
+
+            std::fs::write(build_dir.join(sentinel), "").unwrap();
+            assert!(config.is_configured(), "{backend}");
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let config = test_config()
+            .with_out_path(dir.path())
+            .with_backend(Backend::Vs);
+        std::fs::create_dir_all(config.build_dir().unwrap()).unwrap();
+        std::fs::write(config.build_dir().unwrap().join("build.ninja"), "").unwrap();
+        assert!(!config.is_configured());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_concurrent_configure() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("meson.log");
+        let script = format!(
+            "sleep 0.2\necho \"$1\" >> '{}'\ntouch '{}'",
+            log.display(),
+            dir.path().join("build").join("build.ninja").display()
+        );
+        let config = fake_meson_script_config(dir.path(), &script);
+
+        let configured: Vec<bool> = std::thread::scope(|scope| {
+            let threads: Vec<_> = (0..2)
+                .map(|_| scope.spawn(|| config.configure(dir.path()).unwrap()))
+                .collect();
+            threads.into_iter().map(|t| t.join().unwrap()).collect()
+        });
+
+        assert_eq!(configured.iter().filter(|ran| **ran).count(), 1);
+        assert_eq!(fake_meson_log(dir.path()), ["setup"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_options_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let options_file = dir.path().join("meson_options.txt");
+        std::fs::write(&options_file, "option('a', type: 'boolean')\n").unwrap();
+        let patched = dir.path().join("patched.txt");
+        std::fs::write(&patched, "option('b', type: 'boolean')\n").unwrap();
+
+        // Each invocation logs its arguments and the options file meson would read
+        let mirror = options_file::mirror_path(dir.path());
+        let script = format!(
+            "echo \"$@\" >> '{log}'\ncat '{options}' >> '{log}'",
+            log = dir.path().join("meson.log").display(),
+            options = mirror.join("meson_options.txt").display(),
+        );
+        let config = fake_meson_script_config(dir.path(), &script).with_options_file(&patched);
+
+        config.clone().build(dir.path()).unwrap();
+        let log = fake_meson_log(dir.path());
+        assert!(log[0].starts_with("setup") && log[0].ends_with(&*mirror.to_string_lossy()));
+        // The override is still read when meson regenerates the build files on compile
+        let compile = log
+            .iter()
+            .position(|line| line.starts_with("compile"))
+            .unwrap();
+        assert_eq!(log[compile + 1], "option('b', type: 'boolean')");
+
+        // Rebuilding does not touch the mirror, which would regenerate the build files
+        let modified = |path: &Path| std::fs::metadata(path).unwrap().modified().unwrap();
+        let mirrored = modified(&mirror.join("meson_options.txt"));
+        config.build(dir.path()).unwrap();
+        assert_eq!(modified(&mirror.join("meson_options.txt")), mirrored);
+
+        assert_eq!(
+            std::fs::read_to_string(&options_file).unwrap(),
+            "option('a', type: 'boolean')\n"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_options_file_changes_reconfigure() {
+        let dir = tempfile::tempdir().unwrap();
+        let patched = dir.path().join("patched.txt");
+        std::fs::write(&patched, "option('b', type: 'boolean')\n").unwrap();
+        let config = fake_meson_config(dir.path()).with_options_file(&patched);
+
+        config.configure(dir.path()).unwrap();
+        std::fs::write(config.build_dir().unwrap().join("build.ninja"), "").unwrap();
+        config.configure(dir.path()).unwrap();
+        assert_eq!(fake_meson_log(dir.path()).len(), 1);
+
+        std::fs::write(&patched, "option('c', type: 'boolean')\n").unwrap();
+        config.configure(dir.path()).unwrap();
+        assert_eq!(fake_meson_log(dir.path()).len(), 2);
+        let mirror = options_file::mirror_path(dir.path());
+        assert_eq!(
+            std::fs::read_to_string(mirror.join("meson_options.txt")).unwrap(),
+            "option('c', type: 'boolean')\n"
+        );
+
+        let other = dir.path().join("other.txt");
+        std::fs::copy(&patched, &other).unwrap();
+        let config = config.with_options_file(&other);
+        config.configure(dir.path()).unwrap();
+        assert_eq!(fake_meson_log(dir.path()).len(), 3);
+    }
+
+    #[test]
+    fn test_runtime_library_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = test_config().with_out_path(dir.path());
+        let install_dir = config.install_dir().unwrap();
+        std::fs::create_dir_all(install_dir.join("lib")).unwrap();
+        std::fs::create_dir_all(install_dir.join("bin")).unwrap();
+
+        let config = with_vars(config, &[("CARGO_CFG_TARGET_OS", "linux")]);
+        let lines = config.runtime_library_path().unwrap();
+        assert_eq!(
+            lines,
+            [format!(
+                "cargo:rustc-env=LD_LIBRARY_PATH={}",
+                install_dir.join("lib").display()
+            )]
+        );
+
+        // The current PATH is kept after the install directory.
+        let vars = [("CARGO_CFG_TARGET_OS", "windows"), ("PATH", "/usr/bin")];
+        let lines = with_vars(config, &vars).runtime_library_path().unwrap();
+        let bin_path = format!("cargo:rustc-env=PATH={}", install_dir.join("bin").display());
+        assert!(lines[0].starts_with(&bin_path));
+        assert!(lines[0].ends_with("/usr/bin"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_rebuild_target() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("meson.log");
+        let targets = format!(
+            r#"[{{"name": "foo", "type": "executable", "filename": ["{}/build/foo"], "installed": false}}]"#,
+            dir.path().display()
+        );
+        let script = format!(
+            "echo \"$@\" >> '{}'\n[ \"$1\" = introspect ] && echo '{targets}'\nexit 0",
+            log.display()
+        );
+        let ninja = dir.path().join("ninja");
+        std::fs::write(
+            &ninja,
+            format!("#!/bin/sh\necho ninja \"$@\" >> '{}'\n", log.display()),
+        )
+        .unwrap();
+        std::fs::set_permissions(&ninja, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let config = fake_meson_script_config(dir.path(), &script)
+            .with_ninja_path(&ninja)
+            .with_jobs(2);
+        let build_dir = config.build_dir().unwrap();
+
+        config.rebuild_target("foo").unwrap();
+        assert_eq!(
+            fake_meson_log(dir.path()),
+            [
+                format!("introspect {} --targets", build_dir.display()),
+                format!("ninja -C {} -t clean foo", build_dir.display()),
+                format!("compile -C {} -j 2 foo", build_dir.display()),
+            ]
+        );
+
+        let err = config.rebuild_target("bar").unwrap_err();
+        assert!(matches!(err, Error::TargetNotFound(ref name) if name == "bar"));
+
+        // Neither meson nor ninja run in dry-run mode
+        std::fs::remove_file(&log).unwrap();
+        config
+            .clone()
+            .with_dry_run(true)
+            .rebuild_target("foo")
+            .unwrap();
+        assert!(fake_meson_log(dir.path()).is_empty());
+
+        // Meson reports absolute paths for a relative build directory
+        let cwd = env::current_dir().unwrap();
+        let mut relative_dir: PathBuf = cwd.components().skip(1).map(|_| "..").collect();
+        relative_dir.push(dir.path().strip_prefix("/").unwrap());
+        std::fs::create_dir_all(&build_dir).unwrap();
+        config
+            .clone()
+            .with_out_path(&relative_dir)
+            .rebuild_target("foo")
+            .unwrap();
+        assert_eq!(
+            fake_meson_log(dir.path())[1],
+            format!(
+                "ninja -C {} -t clean foo",
+                relative_dir.join("build").display()
+            )
+        );
+
+        std::fs::remove_file(&log).unwrap();
+        let config = config.with_backend(Backend::Vs);
+        config.rebuild_target("foo").unwrap();
+        assert_eq!(
+            fake_meson_log(dir.path()),
+            [
+                format!("compile -C {} --clean", build_dir.display()),
+                format!("compile -C {} -j 2 foo", build_dir.display()),
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_external_build_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let external = dir.path().join("external");
+        let config = fake_meson_config(dir.path()).with_external_build_dir(&external);
+        assert_eq!(config.build_dir().unwrap(), external);
+
+        let err = config.compile(dir.path()).unwrap_err();
+        assert!(matches!(err, Error::NotConfigured(ref path) if *path == external));
+
+        std::fs::create_dir_all(&external).unwrap();
+        std::fs::write(external.join("build.ninja"), "").unwrap();
+        config.compile(dir.path()).unwrap();
+        assert_eq!(
+            fake_meson_log(dir.path()),
+            [format!("compile -C {}", external.display())]
+        );
+        assert!(!dir.path().join("build").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_sync_options() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("meson.log");
+        let options = r#"[{"name": "buildtype", "value": "release"}, {"name": "docs", "value": false}, {"name": "tests", "value": true}]"#;
+        let script = format!(
+            "[ \"$1\" = introspect ] && echo '{options}' && exit 0\necho \"$@\" >> '{}'",
+            log.display()
+        );
+        let config = fake_meson_script_config(dir.path(), &script).with_option_bool("tests", true);
+        std::fs::create_dir_all(config.build_dir().unwrap()).unwrap();
+        assert!(!config.sync_options().unwrap());
+        assert!(fake_meson_log(dir.path()).is_empty());
+
+        let config = config
+            .with_option_bool("docs", true)
+            .with_option("ssl", "enabled");
+        assert!(config.sync_options().unwrap());
+        assert_eq!(
+            fake_meson_log(dir.path()),
+            [format!(
+                "configure {} -Ddocs=true -Dssl=enabled",
+                config.build_dir().unwrap().display()
+            )]
+        );
+
+        let err = config.with_profile("bench").sync_options().unwrap_err();
+        assert!(matches!(err, Error::InvalidBuildtype(ref profile) if profile == "bench"));
+        assert_eq!(fake_meson_log(dir.path()).len(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_introspect_declared_dependencies() {
+        let dir = tempfile::tempdir().unwrap();
+        let dependencies = r#"[{"name": "zlib", "required": true, "version": [], "has_fallback": false, "conditional": false}]"#;
+        let script = format!(
+            "echo \"$@\" >> '{}'\necho '{dependencies}'",
+            dir.path().join("meson.log").display()
+        );
+        let config = fake_meson_script_config(dir.path(), &script);
+
+        let dependencies = config.introspect_declared_dependencies(dir.path()).unwrap();
+        assert_eq!(dependencies.len(), 1);
+        assert!(dependencies[0].required);
+        assert_eq!(
+            fake_meson_log(dir.path()),
+            [format!(
+                "introspect --dependencies {}",
+                dir.path().join("meson.build").display()
+            )]
+        );
+        // The project is not configured
+        assert!(!config.build_dir().unwrap().exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_sync_options_keeps_setup_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("meson.log");
+        let options =
+            r#"[{"name": "buildtype", "value": "release"}, {"name": "docs", "value": false}]"#;
+        let script = format!(
+            "[ \"$1\" = introspect ] && echo '{options}' && exit 0\necho \"$@\" >> '{}'",
+            log.display()
+        );
+        let config = fake_meson_script_config(dir.path(), &script);
+        let build_dir = config.build_dir().unwrap();
+        std::fs::create_dir_all(&build_dir).unwrap();
+        std::fs::write(build_dir.join("build.ninja"), "").unwrap();
+        config.save_configuration_state().unwrap();
+
+        // The synced options are recorded as applied
+        let config = config.with_option_bool("docs", true);
+        assert!(config.sync_options().unwrap());
+        assert!(!config.configure(dir.path()).unwrap());
+
+        // Unlike the prefix, which only `meson setup` applies
+        let config = config.with_prefix(Path::new("/opt/answer"));
+        assert!(config.sync_options().unwrap());
+        assert!(config.configure(dir.path()).unwrap());
+        let log = fake_meson_log(dir.path());
+        assert!(log.last().unwrap().starts_with("setup --reconfigure"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_subcommand() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = fake_meson_config(dir.path());
+
+        let output = config.run_subcommand(&[OsStr::new("--version")]).unwrap();
+        assert_eq!(output.stdout, b"1.0.0\n");
+        assert_eq!(fake_meson_log(dir.path()), ["--version"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_dry_run() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = fake_meson_config(dir.path()).with_dry_run(true);
+        let options_file = dir.path().join("patched.txt");
+        std::fs::write(&options_file, "option('b', type: 'boolean')\n").unwrap();
+        let entries = || {
+            let mut entries: Vec<_> = std::fs::read_dir(dir.path())
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name())
+                .collect();
+            entries.sort();
+            entries
+        };
+        let before = entries();
+
+        config.clone().build(dir.path()).unwrap();
+        config.run_tests().unwrap();
+        // Listing the subprojects to skip needs introspection
+        config
+            .clone()
+            .with_subproject_filter(&["zlib"])
+            .build(dir.path())
+            .unwrap();
+        config
+            .with_options_file(&options_file)
+            .build(dir.path())
+            .unwrap();
+
+        // No log, lock, build, install or mirror directory
+        assert_eq!(entries(), before);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_compile_and_install() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = fake_meson_config(dir.path());
+
+        config.compile(dir.path()).unwrap();
+        assert!(!config.install_dir().unwrap().exists());
+        let log = fake_meson_log(dir.path());
+        let build_dir = config.build_dir().unwrap();
+        assert!(log
+            .last()
+            .unwrap()
+            .starts_with(&format!("compile -C {}", build_dir.display())));
+        assert!(!log.iter().any(|line| line.starts_with("install")));
+
+        config.install().unwrap();
+        assert!(config.install_dir().unwrap().exists());
+        let log = fake_meson_log(dir.path());
+        assert!(log.last().unwrap().starts_with("install"));
+    }
+
+    #[test]
+    fn test_parse_install_log() {
+        let log = "# List of files installed by Meson\n\
+                   /out/install/lib/libanswer.so\n\
+                   /out/install/include/answer.h\n\
+                   '/out/install/share/with space.txt'\n";
+
+        assert_eq!(
+            parse_install_log(log),
+            [
+                PathBuf::from("/out/install/lib/libanswer.so"),
+                PathBuf::from("/out/install/include/answer.h"),
+                PathBuf::from("/out/install/share/with space.txt"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_installed_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = test_config().with_out_path(dir.path());
+
+        let err = config.installed_files().unwrap_err();
+        assert!(matches!(err, Error::InstallLogNotFound(_)));
+
+        let logs_dir = config.build_dir().unwrap().join("meson-logs");
+        std::fs::create_dir_all(&logs_dir).unwrap();
+        std::fs::write(
+            logs_dir.join("install-log.txt"),
+            "# List of files installed by Meson\n/usr/lib/libfoo.a\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.installed_files().unwrap(),
+            [PathBuf::from("/usr/lib/libfoo.a")]
+        );
+    }
+
+    #[test]
+    fn test_wrap_mode_arg() {
+        let source_dir = Path::new("src");
+        let mut config = test_config().with_profile("release");
+
+        let args = config.setup_args(source_dir).unwrap();
+        assert!(!args.iter().any(|arg| arg == "--wrap-mode"));
+
+        config.set_wrap_mode(WrapMode::NoDownload);
+        let args = config.setup_args(source_dir).unwrap();
+        let position = args.iter().position(|arg| arg == "--wrap-mode").unwrap();
+        assert_eq!(args[position + 1], "nodownload");
+    }
+
+    #[test]
+    fn test_vsenv_arg() {
+        let config = test_config().with_profile("release");
+        let args = config.setup_args(Path::new("src")).unwrap();
+        assert!(!args.iter().any(|arg| arg == "--vsenv"));
+
+        let config = config.with_vsenv(true);
+        let args = config.setup_args(Path::new("src")).unwrap();
+        assert!(args.iter().any(|arg| arg == "--vsenv"));
+    }
+
+    #[test]
+    fn test_extra_setup_args() {
+        let source_dir = Path::new("src");
+        let config = test_config()
+            .with_profile("release")
+            .with_option("foo", "bar")
+            .with_setup_arg("--vsenv")
+            .with_setup_args(&["--pkgconfig.relocatable", "-Dfoo=baz"]);
+
+        let args = config.setup_args(source_dir).unwrap();
+        let build_dir = Path::new("out").join("build");
+        assert_eq!(
+            args[args.len() - 5..],
+            [
+                OsStr::new("--vsenv"),
+                OsStr::new("--pkgconfig.relocatable"),
+                OsStr::new("-Dfoo=baz"),
+                build_dir.as_os_str(),
+                OsStr::new("src"),
+            ]
+        );
+        let generated = args.iter().position(|arg| arg == "-Dfoo=bar").unwrap();
+        assert!(generated < args.len() - 5);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_timeout() {
+        let dir = tempfile::tempdir().unwrap();
+        let pid_file = dir.path().join("meson.pid");
+        let script = format!("echo $$ > '{}'\nexec sleep 10", pid_file.display());
+        let config =
+            fake_meson_script_config(dir.path(), &script).with_timeout(Duration::from_millis(200));
+
+        let start = std::time::Instant::now();
+        let err = config.configure(dir.path()).unwrap_err();
+        assert!(matches!(err, Error::MesonTimedOut(_)));
+        assert!(start.elapsed() < Duration::from_secs(5));
+
+        let pid = std::fs::read_to_string(pid_file).unwrap();
+        let alive = Command::new("kill")
+            .args(["-0", pid.trim()])
+            .stderr(std::process::Stdio::null())
+            .status()
+            .unwrap();
+        assert!(!alive.success());
+    }
+
+    #[test]
+    fn test_compile_commands_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = test_config().with_out_path(dir.path());
+        assert_eq!(config.compile_commands_path(), None);
+
+        let build_dir = config.build_dir().unwrap();
+        std::fs::create_dir_all(&build_dir).unwrap();
+        std::fs::write(build_dir.join("compile_commands.json"), "[]").unwrap();
+
+        assert_eq!(
+            config.compile_commands_path(),
+            Some(build_dir.join("compile_commands.json"))
+        );
+    }
+
+    #[test]
+    fn test_include_metadata() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = test_config().with_out_path(dir.path());
+        assert_eq!(
+            config.include_metadata().unwrap(),
+            format!(
+                "cargo:include={}",
+                dir.path().join("install/include").display()
+            )
+        );
+
+        let config = config.with_includedir(Path::new("include/foo"));
+        assert_eq!(
+            config.include_metadata().unwrap(),
+            format!(
+                "cargo:include={}",
+                dir.path().join("install/include/foo").display()
+            )
+        );
+    }
+
+    #[test]
+    fn test_install_layout_args() {
+        let source_dir = Path::new("src");
+        let config = test_config().with_profile("release");
+        let args = config.setup_args(source_dir).unwrap();
+        for flag in ["--libdir", "--bindir", "--includedir", "--datadir"] {
+            assert!(!args.iter().any(|arg| arg == flag));
+        }
+
+        let config = config
+            .with_libdir(Path::new("lib"))
+            .with_bindir(Path::new("bin"))
+            .with_includedir(Path::new("include/foo"))
+            .with_datadir(Path::new("share"));
+        let args = config.setup_args(source_dir).unwrap();
+        for (flag, value) in [
+            ("--libdir", "lib"),
+            ("--bindir", "bin"),
+            ("--includedir", "include/foo"),
+            ("--datadir", "share"),
+        ] {
+            let position = args.iter().position(|arg| arg == flag).unwrap();
+            assert_eq!(args[position + 1], value);
+        }
+
+        let install_dir = config.install_dir().unwrap();
+        assert_eq!(config.lib_dirs().unwrap(), [install_dir.join("lib")]);
+    }
 
     #[test]
     fn test_get_version_of_meson() {
         let meson_path = "meson";
         let _version =
-            Config::get_version_of_meson(&meson_path).expect("Failed to get Meson version");
+            Config::get_version_of_meson(meson_path, &[]).expect("Failed to get Meson version");
     }
 }
@@ -0,0 +1,156 @@
+//! Async variants of [`Config::build`] and [`Config::build_with_outcome`] for running several
+//! builds concurrently.
+
+use std::{path::Path, process::Command};
+
+use super::Config;
+use crate::{command, lock, lock::FileLock, BuildOutcome, Error, Result};
+
+impl Config {
+    /// Same as [`Config::build`], but meson runs without blocking the async executor.
+    ///
+    /// Must be called within a tokio runtime.
+    pub async fn build_async(self, source_dir: &Path) -> Result<()> {
+        self.run_build_async(source_dir).await?;
+        Ok(())
+    }
+
+    /// Same as [`Config::build_with_outcome`], but meson runs without blocking the async
+    /// executor.
+    ///
+    /// Must be called within a tokio runtime.
+    pub async fn build_with_outcome_async(self, source_dir: &Path) -> Result<BuildOutcome> {
+        let configured = self.run_build_async(source_dir).await?;
+        self.build_outcome(configured)
+    }
+
+    /// Runs the steps of [`Config::run_build`] asynchronously.
+    async fn run_build_async(&self, source_dir: &Path) -> Result<bool> {
+        let configured = self.configure_async(source_dir).await?;
+
+        let command = self.compile_command(source_dir)?;
+        self.run_async(command, |code, stderr| Error::MesonBuildUnsuccessfully {
+            code,
+            stderr,
+        })
+        .await?;
+
+        if self.install {
+            let command = self.prepare_install()?;
+            self.run_async(command, |code, stderr| Error::MesonBuildUnsuccessfully {
+                code,
+                stderr,
+            })
+            .await?;
+        }
+
+        Ok(configured)
+    }
+
+    /// Same as [`Config::configure`], but waits for the lock without blocking.
+    async fn configure_async(&self, source_dir: &Path) -> Result<bool> {
+        let _lock = match self.configure_lock_path()? {
+            None => None,
+            Some(lock_path) => loop {
+                if let Some(lock) = FileLock::try_acquire(&lock_path)? {
+                    break Some(lock);
+                }
+                tokio::time::sleep(lock::RETRY_DELAY).await;
+            },
+        };
+        let Some(command) = self.configure_command(source_dir)? else {
+            return Ok(false);
+        };
+
+        self.run_async(command, |code, stderr| self.configure_failed(code, stderr))
+            .await?;
+
+        self.finish_configure()?;
+        Ok(true)
+    }
+
+    /// Runs a meson command asynchronously, or only prints it in dry-run mode.
+    async fn run_async(
+        &self,
+        command: Command,
+        failed: impl FnOnce(i32, String) -> Error,
+    ) -> Result<()> {
+        if self.dry_run {
+            self.print(&format!("Dry run: {}", command::command_line(&command)));
+            return Ok(());
+        }
+
+        command::run_async(
+            command,
+            self.timeout,
+            self.output_callback.as_ref(),
+            self.progress_callback.as_ref(),
+            failed,
+        )
+        .await?;
+        Ok(())
+    }
+}
+
+/// The tests rely on a fake meson shell script.
+#[cfg(all(test, unix))]
+mod tests {
+    use crate::{config::tests::fake_meson_script_config, Error};
+
+    #[tokio::test]
+    async fn test_build_async() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("meson.log");
+        let script = format!("echo \"$1\" >> '{}'", log.display());
+        let config = fake_meson_script_config(dir.path(), &script);
+
+        config.build_async(dir.path()).await.unwrap();
+
+        let log = std::fs::read_to_string(log).unwrap();
+        assert_eq!(
+            log.lines().collect::<Vec<_>>(),
+            ["setup", "compile", "install"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_build_with_outcome_async() {
+        let dir = tempfile::tempdir().unwrap();
+        let script = r#"if [ "$1" = install ]; then
+    mkdir -p "$3/meson-logs"
+    printf '# List of files installed by Meson\n/prefix/lib/libanswer.a\n' > "$3/meson-logs/install-log.txt"
+fi"#;
+        let config = fake_meson_script_config(dir.path(), script);
+
+        let outcome = config.build_with_outcome_async(dir.path()).await.unwrap();
+        assert!(outcome.configured);
+        assert_eq!(
+            outcome.installed_files,
+            [std::path::PathBuf::from("/prefix/lib/libanswer.a")]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_build_async_dry_run() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("out");
+        let config = fake_meson_script_config(dir.path(), "exit 1")
+            .with_out_path(&out)
+            .with_dry_run(true);
+
+        config.build_async(dir.path()).await.unwrap();
+        assert!(!out.exists());
+    }
+
+    #[tokio::test]
+    async fn test_build_async_failure() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = fake_meson_script_config(dir.path(), "echo broken >&2\nexit 3");
+
+        let err = config.build_async(dir.path()).await.unwrap_err();
+        assert!(matches!(
+            err,
+            Error::MesonConfiguredUnsuccessfully { code: 3, ref stderr, .. } if stderr == "broken\n"
+        ));
+    }
+}
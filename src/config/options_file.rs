@@ -0,0 +1,164 @@
+//! Mirror of a source tree with its options file replaced, leaving the source tree untouched.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::Result;
+
+/// Options file names recognized by meson, the newer one first.
+const OPTIONS_FILES: [&str; 2] = ["meson.options", "meson_options.txt"];
+
+/// Updates `mirror` to link each entry of `source_dir`, except its options file which is a
+/// copy of `file`.
+///
+/// Meson configured from the mirror reads `file`, also when it regenerates the build files
+/// later. Unchanged entries are kept, so the build files are not regenerated needlessly. If
+/// the tree has no options file, `meson_options.txt` is created as it is understood by all
+/// meson versions.
+pub(super) fn mirror_source_tree(source_dir: &Path, mirror: &Path, file: &Path) -> Result<()> {
+    fs::create_dir_all(mirror)?;
+
+    let mut options_files: Vec<&str> = OPTIONS_FILES
+        .into_iter()
+        .filter(|name| source_dir.join(name).is_file())
+        .collect();
+    if options_files.is_empty() {
+        options_files.push(OPTIONS_FILES[1]);
+    }
+
+    let contents = fs::read(file)?;
+    for name in &options_files {
+        let target = mirror.join(name);
+        if fs::symlink_metadata(&target).is_ok_and(|metadata| metadata.file_type().is_symlink()) {
+            fs::remove_file(&target)?;
+        }
+        if fs::read(&target).ok().as_ref() != Some(&contents) {
+            fs::write(&target, &contents)?;
+        }
+    }
+
+    let canonical_mirror = mirror.canonicalize()?;
+    let mut linked = Vec::new();
+    for entry in fs::read_dir(source_dir)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        if options_files
+            .iter()
+            .any(|options_file| name == *options_file)
+        {
+            continue;
+        }
+
+        // The mirror may be created inside the source tree, e.g. in tests
+        let source = entry.path().canonicalize()?;
+        if canonical_mirror.starts_with(&source) {
+            continue;
+        }
+
+        let link = mirror.join(&name);
+        if fs::read_link(&link).ok().as_ref() != Some(&source) {
+            remove_entry(&link)?;
+            symlink(&source, &link, entry.file_type()?.is_dir())?;
+        }
+        linked.push(name);
+    }
+
+    // Entries removed from the source tree
+    for entry in fs::read_dir(mirror)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        if !linked.contains(&name) && !options_files.iter().any(|file| name == *file) {
+            remove_entry(&entry.path())?;
+        }
+    }
+
+    Ok(())
+}
+
+/// The mirror of `source_dir` used with an options file, inside `out_path`.
+pub(super) fn mirror_path(out_path: &Path) -> PathBuf {
+    out_path.join("meson-source")
+}
+
+/// Removes the file or link at `path`, if any.
+fn remove_entry(path: &Path) -> io::Result<()> {
+    let Ok(metadata) = fs::symlink_metadata(path) else {
+        return Ok(());
+    };
+
+    if metadata.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        remove_link(path)
+    }
+}
+
+#[cfg(unix)]
+fn remove_link(path: &Path) -> io::Result<()> {
+    fs::remove_file(path)
+}
+
+/// Directory links are removed as directories on Windows.
+#[cfg(windows)]
+fn remove_link(path: &Path) -> io::Result<()> {
+    fs::remove_file(path).or_else(|_| fs::remove_dir(path))
+}
+
+#[cfg(unix)]
+fn symlink(source: &Path, link: &Path, _is_dir: bool) -> io::Result<()> {
+    std::os::unix::fs::symlink(source, link)
+}
+
+/// Needs the developer mode or the privilege to create symbolic links.
+#[cfg(windows)]
+fn symlink(source: &Path, link: &Path, is_dir: bool) -> io::Result<()> {
+    if is_dir {
+        std::os::windows::fs::symlink_dir(source, link)
+    } else {
+        std::os::windows::fs::symlink_file(source, link)
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mirror_source_tree() {
+        let source = tempfile::tempdir().unwrap();
+        let out = tempfile::tempdir().unwrap();
+        let mirror = mirror_path(out.path());
+        let file = out.path().join("override.options");
+        fs::write(&file, "override").unwrap();
+        fs::write(source.path().join("meson.build"), "project('answer')").unwrap();
+        fs::create_dir(source.path().join("src")).unwrap();
+
+        mirror_source_tree(source.path(), &mirror, &file).unwrap();
+        assert_eq!(
+            fs::read_to_string(mirror.join("meson_options.txt")).unwrap(),
+            "override"
+        );
+        assert!(!source.path().join("meson_options.txt").exists());
+        assert_eq!(
+            fs::read_link(mirror.join("src")).unwrap(),
+            source.path().join("src").canonicalize().unwrap()
+        );
+
+        // The existing options file is replaced, removed entries are unlinked
+        fs::write(source.path().join("meson.options"), "original").unwrap();
+        fs::remove_dir(source.path().join("src")).unwrap();
+        mirror_source_tree(source.path(), &mirror, &file).unwrap();
+        assert_eq!(
+            fs::read_to_string(mirror.join("meson.options")).unwrap(),
+            "override"
+        );
+        assert_eq!(
+            fs::read_to_string(source.path().join("meson.options")).unwrap(),
+            "original"
+        );
+        assert!(!mirror.join("src").exists());
+        assert!(!mirror.join("meson_options.txt").exists());
+    }
+}
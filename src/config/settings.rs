@@ -0,0 +1,164 @@
+//! Serialization of the user-settable part of [`Config`].
+//!
+//! The meson executable is not serialized: deserialization resolves it using
+//! [`Config::find_system_meson`].
+
+use std::{collections::BTreeMap, path::PathBuf};
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use super::Config;
+use crate::Backend;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct Settings {
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    options: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    profile: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty", with = "one_or_many")]
+    native_file: Vec<PathBuf>,
+    #[serde(skip_serializing_if = "Vec::is_empty", with = "one_or_many")]
+    cross_file: Vec<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    backend: Option<Backend>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    jobs: Option<usize>,
+}
+
+impl Serialize for Config {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        Settings {
+            options: self.options.clone(),
+            profile: self.profile.clone(),
+            native_file: self.native_files.clone(),
+            cross_file: self.cross_files.clone(),
+            backend: self.backend,
+            jobs: self.jobs,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Config {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let settings = Settings::deserialize(deserializer)?;
+
+        let mut config = Config::find_system_meson().map_err(de::Error::custom)?;
+        config.options = settings.options;
+        config.profile = settings.profile;
+        config.native_files = settings.native_file;
+        config.cross_files = settings.cross_file;
+        config.backend = settings.backend;
+        config.jobs = settings.jobs;
+
+        Ok(config)
+    }
+}
+
+/// Machine files written as a single path, or as an array of paths when layered.
+mod one_or_many {
+    use std::path::PathBuf;
+
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(PathBuf),
+        Many(Vec<PathBuf>),
+    }
+
+    pub(super) fn serialize<S: Serializer>(
+        files: &[PathBuf],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match files {
+            [file] => file.serialize(serializer),
+            files => files.serialize(serializer),
+        }
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<PathBuf>, D::Error> {
+        Ok(match OneOrMany::deserialize(deserializer)? {
+            OneOrMany::One(file) => vec![file],
+            OneOrMany::Many(files) => files,
+        })
+    }
+}
+
+/// The tests rely on a fake meson shell script.
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    use std::path::Path;
+
+    use crate::config::tests::{fake_meson_script_config, test_config, with_env};
+
+    /// Deserializes a config using a fake meson found through `MESON`.
+    fn deserialize_with_fake_meson<T>(f: impl FnOnce() -> T) -> T {
+        let dir = tempfile::tempdir().unwrap();
+        let meson = fake_meson_script_config(dir.path(), "echo 1.2.3").meson_path;
+        let meson = meson.to_str().unwrap();
+
+        with_env(&[("MESON", Some(meson)), ("TARGET", None)], f)
+    }
+
+    #[test]
+    fn test_serde_round_trip() {
+        let config = test_config()
+            .with_option("foo", "bar")
+            .with_profile("debugoptimized")
+            .with_native_file(Path::new("native.ini"))
+            .with_cross_file(Path::new("cross.ini"))
+            .with_backend(Backend::Ninja)
+            .with_jobs(4);
+
+        let json = serde_json::to_string(&config).unwrap();
+        assert_eq!(
+            json,
+            r#"{"options":{"foo":"bar"},"profile":"debugoptimized","native_file":"native.ini","cross_file":"cross.ini","backend":"ninja","jobs":4}"#
+        );
+
+        let loaded: Config = deserialize_with_fake_meson(|| serde_json::from_str(&json).unwrap());
+        assert_eq!(loaded.meson_version(), "1.2.3");
+        assert_eq!(serde_json::to_string(&loaded).unwrap(), json);
+
+        let config = test_config()
+            .with_native_file(Path::new("base.ini"))
+            .with_added_native_file(Path::new("override.ini"));
+        let json = serde_json::to_string(&config).unwrap();
+        assert_eq!(json, r#"{"native_file":["base.ini","override.ini"]}"#);
+        let loaded: Config = deserialize_with_fake_meson(|| serde_json::from_str(&json).unwrap());
+        assert_eq!(loaded.native_files, config.native_files);
+    }
+
+    #[test]
+    fn test_serde_from_toml() {
+        let toml = r#"
+            profile = "release"
+            backend = "vs2022"
+            jobs = 2
+
+            [options]
+            docs = "false"
+            ssl = "enabled"
+        "#;
+
+        let config: Config = deserialize_with_fake_meson(|| toml::from_str(toml).unwrap());
+
+        assert_eq!(config.profile.as_deref(), Some("release"));
+        assert_eq!(config.backend, Some(Backend::Vs2022));
+        assert_eq!(config.jobs, Some(2));
+        assert_eq!(config.options["docs"], "false");
+        assert_eq!(config.options["ssl"], "enabled");
+        assert!(config.native_files.is_empty());
+
+        let err = deserialize_with_fake_meson(|| toml::from_str::<Config>("unknown = 1"));
+        assert!(err.is_err());
+    }
+}
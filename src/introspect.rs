@@ -0,0 +1,456 @@
+//! Data returned by `meson introspect`.
+//!
+//! The output is read as untyped JSON, so that parsing does not depend on the optional `serde`
+//! feature.
+
+use std::{collections::HashMap, path::PathBuf};
+
+use serde_json::Value;
+
+use crate::{options, Error, Result};
+
+/// A build target of the meson project: see `meson introspect --targets`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Target {
+    /// Name of the target as in `meson.build`.
+    pub name: String,
+
+    /// Type of the target, e.g. `executable`, `static library`, `shared library`.
+    pub target_type: String,
+
+    /// Paths of the files produced by the target.
+    pub filename: Vec<PathBuf>,
+
+    /// Whether the target is installed by `meson install`.
+    pub installed: bool,
+}
+
+pub(crate) fn parse_targets(json: &[u8]) -> Result<Vec<Target>> {
+    parse_array(json)?
+        .iter()
+        .map(|target| {
+            Ok(Target {
+                name: string_field(target, "name")?,
+                target_type: string_field(target, "type")?,
+                filename: strings(field(target, "filename")?, "filename")?
+                    .into_iter()
+                    .map(PathBuf::from)
+                    .collect(),
+                installed: bool_field(target, "installed")?,
+            })
+        })
+        .collect()
+}
+
+/// An external dependency of the configured meson project: see
+/// `meson introspect --dependencies`.
+///
+/// Meson does not report whether a dependency of a build directory is required, see
+/// [`DeclaredDependency`] for that.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dependency {
+    /// Name of the dependency as passed to `dependency()`.
+    pub name: String,
+
+    /// Version of the found dependency, empty or `unknown` if meson can not tell.
+    pub version: String,
+
+    /// Whether the dependency was found.
+    ///
+    /// If not reported by meson, a dependency is found if it has a type other than `not-found`.
+    pub found: bool,
+}
+
+pub(crate) fn parse_dependencies(json: &[u8]) -> Result<Vec<Dependency>> {
+    parse_array(json)?
+        .iter()
+        .map(|dependency| {
+            let found = match optional_bool_field(dependency, "found")? {
+                Some(found) => found,
+                None => optional_string_field(dependency, "type")?
+                    .is_some_and(|dependency_type| dependency_type != "not-found"),
+            };
+
+            Ok(Dependency {
+                name: string_field(dependency, "name")?,
+                version: optional_string_field(dependency, "version")?.unwrap_or_default(),
+                found,
+            })
+        })
+        .collect()
+}
+
+/// A dependency declared in the `meson.build` files of a project: see
+/// `meson introspect --dependencies meson.build`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeclaredDependency {
+    /// Name of the dependency as passed to `dependency()`.
+    pub name: String,
+
+    /// Version requirements of the dependency, e.g. `>=1.2`.
+    pub version: Vec<String>,
+
+    /// Whether configuring fails without the dependency.
+    ///
+    /// `false` if `required` depends on the configuration, e.g. a feature option.
+    pub required: bool,
+
+    /// Whether a subproject provides the dependency if it is not found.
+    pub has_fallback: bool,
+
+    /// Whether the dependency is declared inside a conditional block.
+    pub conditional: bool,
+}
+
+pub(crate) fn parse_declared_dependencies(json: &[u8]) -> Result<Vec<DeclaredDependency>> {
+    parse_array(json)?
+        .iter()
+        .map(|dependency| {
+            // A single requirement may be given as a string
+            let version = match field(dependency, "version")? {
+                Value::String(version) => vec![version.clone()],
+                version => strings(version, "version")?,
+            };
+
+            Ok(DeclaredDependency {
+                name: string_field(dependency, "name")?,
+                version,
+                required: bool_field(dependency, "required")?,
+                has_fallback: bool_field(dependency, "has_fallback")?,
+                conditional: bool_field(dependency, "conditional")?,
+            })
+        })
+        .collect()
+}
+
+/// Parses the names of the subprojects used by the project, listed by
+/// `meson introspect --projectinfo`.
+pub(crate) fn parse_subprojects(json: &[u8]) -> Result<Vec<String>> {
+    let info: Value = serde_json::from_slice(json)?;
+
+    match info.get("subprojects") {
+        None | Some(Value::Null) => Ok(Vec::new()),
+        Some(Value::Array(subprojects)) => subprojects
+            .iter()
+            .map(|subproject| string_field(subproject, "name"))
+            .collect(),
+        Some(_) => Err(Error::IntrospectField("subprojects".to_string())),
+    }
+}
+
+/// Parses build options listed by `meson introspect --buildoptions` into values formatted as
+/// they are passed to `-D`.
+pub(crate) fn parse_build_options(json: &[u8]) -> Result<HashMap<String, String>> {
+    parse_array(json)?
+        .iter()
+        .map(|option| {
+            Ok((
+                string_field(option, "name")?,
+                format_value(field(option, "value")?),
+            ))
+        })
+        .collect()
+}
+
+fn format_value(value: &Value) -> String {
+    match value {
+        Value::String(value) => value.clone(),
+        Value::Array(values) => {
+            let values: Vec<String> = values.iter().map(format_value).collect();
+            options::format_array(&values)
+        }
+        value => value.to_string(),
+    }
+}
+
+fn parse_array(json: &[u8]) -> Result<Vec<Value>> {
+    Ok(serde_json::from_slice(json)?)
+}
+
+/// The `name` field of a JSON object, failing with [`Error::IntrospectField`] if missing.
+pub(crate) fn field<'a>(object: &'a Value, name: &str) -> Result<&'a Value> {
+    object
+        .get(name)
+        .ok_or_else(|| Error::IntrospectField(name.to_string()))
+}
+
+/// The `name` string field of a JSON object.
+pub(crate) fn string_field(object: &Value, name: &str) -> Result<String> {
+    optional_string_field(object, name)?.ok_or_else(|| Error::IntrospectField(name.to_string()))
+}
+
+/// The `name` string field of a JSON object, `None` if missing or null.
+pub(crate) fn optional_string_field(object: &Value, name: &str) -> Result<Option<String>> {
+    match object.get(name) {
+        None | Some(Value::Null) => Ok(None),
+        Some(Value::String(value)) => Ok(Some(value.clone())),
+        Some(_) => Err(Error::IntrospectField(name.to_string())),
+    }
+}
+
+/// The `name` boolean field of a JSON object.
+fn bool_field(object: &Value, name: &str) -> Result<bool> {
+    optional_bool_field(object, name)?.ok_or_else(|| Error::IntrospectField(name.to_string()))
+}
+
+/// The `name` boolean field of a JSON object, `None` if missing or null.
+fn optional_bool_field(object: &Value, name: &str) -> Result<Option<bool>> {
+    match object.get(name) {
+        None | Some(Value::Null) => Ok(None),
+        Some(Value::Bool(value)) => Ok(Some(*value)),
+        Some(_) => Err(Error::IntrospectField(name.to_string())),
+    }
+}
+
+/// A JSON array of strings, `name` is the field it was read from.
+fn strings(value: &Value, name: &str) -> Result<Vec<String>> {
+    let invalid = || Error::IntrospectField(name.to_string());
+    value
+        .as_array()
+        .ok_or_else(invalid)?
+        .iter()
+        .map(|value| value.as_str().map(str::to_string).ok_or_else(invalid))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Output of `meson introspect --targets` captured from a project with a shared library
+    /// and a test executable.
+    const TARGETS_JSON: &str = r#"[
+  {
+    "name": "answer",
+    "id": "answer@sha",
+    "type": "shared library",
+    "defined_in": "/src/answer/meson.build",
+    "filename": ["/src/answer/build/libanswer.so"],
+    "build_by_default": true,
+    "target_sources": [
+      {
+        "language": "c",
+        "compiler": ["cc"],
+        "parameters": ["-O2"],
+        "sources": ["/src/answer/answer.c"],
+        "generated_sources": []
+      }
+    ],
+    "extra_files": [],
+    "subproject": null,
+    "dependencies": [],
+    "installed": true,
+    "install_filename": ["/usr/local/lib/libanswer.so"]
+  },
+  {
+    "name": "answer_test",
+    "id": "answer_test@exe",
+    "type": "executable",
+    "defined_in": "/src/answer/meson.build",
+    "filename": ["/src/answer/build/answer_test"],
+    "build_by_default": true,
+    "target_sources": [],
+    "extra_files": [],
+    "subproject": null,
+    "dependencies": [],
+    "installed": false
+  }
+]"#;
+
+    /// Part of `meson introspect --buildoptions` output with each kind of option value.
+    const BUILD_OPTIONS_JSON: &str = r#"[
+  {
+    "name": "buildtype",
+    "value": "release",
+    "section": "core",
+    "machine": "any",
+    "type": "combo",
+    "description": "Build type to use",
+    "choices": ["plain", "debug", "debugoptimized", "release", "minsize", "custom"]
+  },
+  {
+    "name": "b_lto",
+    "value": false,
+    "section": "base",
+    "machine": "any",
+    "type": "boolean",
+    "description": "Use link time optimization"
+  },
+  {
+    "name": "warning_level",
+    "value": "1",
+    "section": "core",
+    "machine": "any",
+    "type": "combo",
+    "description": "Compiler warning level to use",
+    "choices": ["0", "1", "2", "3", "everything"]
+  },
+  {
+    "name": "backend_max_links",
+    "value": 0,
+    "section": "backend",
+    "machine": "any",
+    "type": "integer",
+    "description": "Maximum number of linker processes to run or 0 for no limit"
+  },
+  {
+    "name": "c_args",
+    "value": ["-DANSWER=42", "-O2"],
+    "section": "compiler",
+    "machine": "host",
+    "type": "array",
+    "description": "Extra arguments passed to the c compiler"
+  },
+  {
+    "name": "tests",
+    "value": "auto",
+    "section": "user",
+    "machine": "any",
+    "type": "feature",
+    "description": "Build tests",
+    "choices": ["enabled", "disabled", "auto"]
+  }
+]"#;
+
+    #[test]
+    fn test_parse_dependencies() {
+        // A dependency as listed by meson, one reporting the optional fields and one without
+        // any of them
+        let json = r#"[
+  {
+    "name": "zlib",
+    "type": "pkgconfig",
+    "version": "1.3.1",
+    "compile_args": [],
+    "link_args": ["/usr/lib/libz.so"],
+    "include_directories": [],
+    "sources": [],
+    "extra_files": [],
+    "dependencies": [],
+    "depends": [],
+    "meson_variables": []
+  },
+  {
+    "name": "libpng",
+    "type": "not-found",
+    "version": "unknown",
+    "found": false
+  },
+  {
+    "name": "libjpeg"
+  }
+]"#;
+
+        let dependencies = parse_dependencies(json.as_bytes()).unwrap();
+        assert_eq!(
+            dependencies,
+            [
+                Dependency {
+                    name: "zlib".to_string(),
+                    version: "1.3.1".to_string(),
+                    found: true,
+                },
+                Dependency {
+                    name: "libpng".to_string(),
+                    version: "unknown".to_string(),
+                    found: false,
+                },
+                Dependency {
+                    name: "libjpeg".to_string(),
+                    version: String::new(),
+                    found: false,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_declared_dependencies() {
+        // Output of `meson introspect --dependencies meson.build`
+        let json = r#"[
+  {"name": "zlib", "required": true, "version": [">=1.2"], "has_fallback": false, "conditional": false},
+  {"name": "libpng", "required": false, "version": [], "has_fallback": true, "conditional": true}
+]"#;
+
+        let dependencies = parse_declared_dependencies(json.as_bytes()).unwrap();
+        assert_eq!(
+            dependencies,
+            [
+                DeclaredDependency {
+                    name: "zlib".to_string(),
+                    version: vec![">=1.2".to_string()],
+                    required: true,
+                    has_fallback: false,
+                    conditional: false,
+                },
+                DeclaredDependency {
+                    name: "libpng".to_string(),
+                    version: Vec::new(),
+                    required: false,
+                    has_fallback: true,
+                    conditional: true,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_subprojects() {
+        let json = r#"{
+  "version": "1.0.0",
+  "descriptive_name": "answer",
+  "subproject_dir": "subprojects",
+  "subprojects": [
+    {"name": "zlib", "version": "1.3.1", "descriptive_name": "zlib"},
+    {"name": "gtest", "version": "1.14.0", "descriptive_name": "gtest"}
+  ]
+}"#;
+
+        let subprojects = parse_subprojects(json.as_bytes()).unwrap();
+        assert_eq!(subprojects, ["zlib", "gtest"]);
+    }
+
+    #[test]
+    fn test_parse_build_options() {
+        let options = parse_build_options(BUILD_OPTIONS_JSON.as_bytes()).unwrap();
+
+        let expected = [
+            ("buildtype", "release"),
+            ("b_lto", "false"),
+            ("warning_level", "1"),
+            ("backend_max_links", "0"),
+            ("c_args", "['-DANSWER=42','-O2']"),
+            ("tests", "auto"),
+        ];
+        assert_eq!(options.len(), expected.len());
+        for (name, value) in expected {
+            assert_eq!(options[name], value, "{name}");
+        }
+    }
+
+    #[test]
+    fn test_parse_targets() {
+        let targets = parse_targets(TARGETS_JSON.as_bytes()).unwrap();
+
+        assert_eq!(
+            targets,
+            [
+                Target {
+                    name: "answer".to_string(),
+                    target_type: "shared library".to_string(),
+                    filename: vec!["/src/answer/build/libanswer.so".into()],
+                    installed: true,
+                },
+                Target {
+                    name: "answer_test".to_string(),
+                    target_type: "executable".to_string(),
+                    filename: vec!["/src/answer/build/answer_test".into()],
+                    installed: false,
+                },
+            ]
+        );
+
+        let err = parse_targets(br#"[{"name": "answer", "type": "executable"}]"#).unwrap_err();
+        assert!(matches!(err, Error::IntrospectField(ref field) if field == "filename"));
+    }
+}
@@ -1,32 +1,135 @@
+//! A library for building native dependencies using the Meson build system.
+//!
+//! # Features
+//!
+//! - `serde`: implements `Serialize` and `Deserialize` for the user-settable parts of
+//!   [`Config`], to load them from a configuration file.
+//! - `async`: adds `Config::build_async` and `Config::build_with_outcome_async`, running meson
+//!   with tokio.
+
+mod backend;
+mod cargo;
+mod command;
+mod compiler_cache;
 mod config;
-use std::str::Utf8Error;
+mod introspect;
+mod lock;
+mod machine_file;
+mod options;
+mod outcome;
+mod test_log;
+use std::{path::PathBuf, str::Utf8Error};
 
+pub use backend::Backend;
+pub use compiler_cache::CompilerCache;
 pub use config::Config;
+pub use introspect::{DeclaredDependency, Dependency, Target};
+pub use options::{Feature, LibraryKind, LtoMode, Sanitizer, UnityMode, WrapMode};
+pub use outcome::BuildOutcome;
+pub use test_log::TestResult;
 
 /// Errors generated by this crate.
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
-    #[error("Meson not found")]
-    MesonNotFound,
+    #[error("Meson not found at {tried}, install meson or set the MESON environment variable")]
+    MesonNotFound { tried: PathBuf },
+
+    #[error("Meson is not executable: {0}")]
+    MesonNotExecutable(PathBuf),
 
     #[error("The meson returned unexpected version output: {0}")]
     MesonIncorrectVersion(#[from] semver::Error),
 
-    #[error("Meson exited unexpectedly: {0}")]
-    MesonExitedUnsuccessfully(i32),
+    #[error("Meson version {found} does not satisfy the requirement '{required}'")]
+    MesonVersionTooOld {
+        found: semver::Version,
+        required: semver::VersionReq,
+    },
+
+    #[error("Invalid meson version requirement: {0}")]
+    InvalidVersionRequirement(#[source] semver::Error),
+
+    #[error("Meson exited unexpectedly: {code}\n{}", command::stderr_tail(.stderr))]
+    MesonExitedUnsuccessfully { code: i32, stderr: String },
+
+    #[error("Meson did not finish in {0:?}")]
+    MesonTimedOut(std::time::Duration),
 
     #[error("Meson exited with signal")]
     MesonExitedBySignal,
 
-    #[error("Meson configure failed: {0}")]
-    MesonConfiguredUnsuccessfully(i32),
+    #[error(
+        "Meson configure failed: {code}\n{}{}",
+        command::stderr_tail(.stderr),
+        command::log_hint(.log_path.as_deref())
+    )]
+    MesonConfiguredUnsuccessfully {
+        code: i32,
+        stderr: String,
+        /// `meson-logs/meson-log.txt` of the build directory, if meson wrote it.
+        log_path: Option<PathBuf>,
+    },
+
+    #[error("Meson build failed: {code}\n{}", command::stderr_tail(.stderr))]
+    MesonBuildUnsuccessfully { code: i32, stderr: String },
+
+    #[error("Unknown meson backend: {0}")]
+    UnknownBackend(String),
+
+    #[error("Unknown meson buildtype: {0}")]
+    InvalidBuildtype(String),
+
+    #[error("Invalid warning level {0}, expected 0 to 4")]
+    InvalidWarningLevel(u8),
+
+    #[error("No meson.build file in the source directory: {0}")]
+    NoMesonBuildFile(PathBuf),
 
-    #[error("Meson build failed: {0}")]
-    MesonBuildUnsuccessfully(i32),
+    #[error("The build directory is not configured: {0}")]
+    NotConfigured(PathBuf),
+
+    #[error("No target named {0} in the configured project")]
+    TargetNotFound(String),
+
+    #[error("Meson tests failed: {0}")]
+    MesonTestsFailed(i32),
+
+    #[error("Install log {0} not found. Was the project installed?")]
+    InstallLogNotFound(PathBuf),
+
+    #[error("No {format} archive produced by meson dist in {dir}")]
+    DistArchiveNotFound { dir: PathBuf, format: String },
+
+    #[error("OUT_DIR is not set. Are you running outside of build.rs?")]
+    OutDirNotSet,
+
+    #[error("TARGET is not set. Are you running outside of build.rs?")]
+    TargetNotSet,
+
+    #[error("Target '{0}' is not supported for cross file generation")]
+    UnsupportedTarget(String),
 
     #[error("IO error: {0}")]
     IoError(#[from] std::io::Error),
 
+    #[error("Failed to run {program}: {source}")]
+    CommandSpawn {
+        program: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[error("pkg-config file for package '{0}' not found in the install directory")]
+    PkgConfigFileNotFound(String),
+
+    #[error("pkg-config error: {0}")]
+    PkgConfig(#[from] pkg_config::Error),
+
+    #[error("Unexpected meson introspect output: {0}")]
+    IntrospectParse(#[from] serde_json::Error),
+
+    #[error("Missing or invalid field '{0}' in the meson output")]
+    IntrospectField(String),
+
     #[error("Utf8 error")]
     Utf8Error(#[from] Utf8Error),
 }
@@ -46,3 +149,100 @@ pub type Result<T> = std::result::Result<T, Error>;
 pub fn find_meson() -> Result<Config> {
     Config::find_system_meson()
 }
+
+/// Finds the ninja executable to pass into [`Config::set_ninja_path`].
+///
+/// Same as [`find_meson`], `NINJA_<TARGET_UPPER_CASE>` and then `NINJA` environment variables
+/// are checked before falling back to `ninja` in PATH.
+pub fn find_ninja() -> Result<PathBuf> {
+    Ok(config::program_from_env("NINJA", "ninja"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_display_stderr() {
+        let stderr = "The Meson build system\nERROR: Unknown options: \"foo\"\n".to_string();
+
+        let errors = [
+            Error::MesonConfiguredUnsuccessfully {
+                code: 1,
+                stderr: stderr.clone(),
+                log_path: None,
+            },
+            Error::MesonBuildUnsuccessfully {
+                code: 1,
+                stderr: stderr.clone(),
+            },
+            Error::MesonExitedUnsuccessfully {
+                code: 1,
+                stderr: stderr.clone(),
+            },
+        ];
+
+        for err in errors {
+            assert!(err.to_string().contains("ERROR: Unknown options: \"foo\""));
+        }
+    }
+
+    #[test]
+    fn test_error_source() {
+        use std::error::Error as _;
+
+        let io = || std::io::Error::new(std::io::ErrorKind::PermissionDenied, "denied");
+        let semver = || semver::Version::parse("not a version").unwrap_err();
+        let bytes = vec![0xff];
+        let utf8 = std::str::from_utf8(&bytes).unwrap_err();
+
+        let errors = [
+            Error::from(io()),
+            Error::CommandSpawn {
+                program: "meson".into(),
+                source: io(),
+            },
+            Error::from(semver()),
+            Error::InvalidVersionRequirement(semver()),
+            Error::from(utf8),
+        ];
+
+        for err in errors {
+            let source = err.source().unwrap_or_else(|| panic!("no source: {err:?}"));
+            assert!(
+                source.is::<std::io::Error>()
+                    || source.is::<semver::Error>()
+                    || source.is::<Utf8Error>()
+            );
+        }
+        assert!(Error::OutDirNotSet.source().is_none());
+    }
+
+    #[test]
+    fn test_error_display_truncates_stderr() {
+        let stderr: String = (0..100).map(|line| format!("line {line}\n")).collect();
+        let err = Error::MesonBuildUnsuccessfully { code: 2, stderr };
+
+        let message = err.to_string();
+        assert!(message.starts_with("Meson build failed: 2\n"));
+        assert!(message.ends_with("line 99"));
+        assert!(!message.contains("line 0\n"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_find_meson() {
+        use config::tests::{fake_meson_script_config, with_env};
+
+        let dir = tempfile::tempdir().unwrap();
+        fake_meson_script_config(dir.path(), "echo 1.3.2");
+        let meson = dir.path().join("meson");
+
+        let (found, system) = with_env(&[("MESON", meson.to_str()), ("TARGET", None)], || {
+            (find_meson().unwrap(), Config::find_system_meson().unwrap())
+        });
+        assert_eq!(found.meson_version(), "1.3.2");
+        assert_eq!(found.meson_version(), system.meson_version());
+        assert_eq!(system.meson_path(), meson);
+    }
+}
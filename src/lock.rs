@@ -0,0 +1,92 @@
+//! Advisory lock serializing the configuration of a build directory shared by several processes.
+
+use std::{
+    fs::{File, OpenOptions},
+    io,
+    path::Path,
+    time::Duration,
+};
+
+/// Delay between attempts to take a lock held by another process.
+pub(crate) const RETRY_DELAY: Duration = Duration::from_millis(50);
+
+/// A lock released when dropped, or when the owning process exits.
+#[derive(Debug)]
+pub(crate) struct FileLock {
+    _file: File,
+}
+
+impl FileLock {
+    /// Takes the lock on `path`, waiting while it is held.
+    pub(crate) fn acquire(path: &Path) -> io::Result<Self> {
+        loop {
+            if let Some(lock) = Self::try_acquire(path)? {
+                return Ok(lock);
+            }
+            std::thread::sleep(RETRY_DELAY);
+        }
+    }
+
+    /// Takes the lock on `path`, `None` if it is held.
+    #[cfg(unix)]
+    pub(crate) fn try_acquire(path: &Path) -> io::Result<Option<Self>> {
+        use std::os::unix::io::AsRawFd;
+
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(path)?;
+
+        // SAFETY: the descriptor stays open for the duration of the call.
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == 0 {
+            return Ok(Some(Self { _file: file }));
+        }
+
+        let err = io::Error::last_os_error();
+        if err.kind() == io::ErrorKind::WouldBlock {
+            Ok(None)
+        } else {
+            Err(err)
+        }
+    }
+
+    /// Takes the lock on `path`, `None` if it is held.
+    ///
+    /// The file is opened without sharing, so other handles fail to open it.
+    #[cfg(windows)]
+    pub(crate) fn try_acquire(path: &Path) -> io::Result<Option<Self>> {
+        use std::os::windows::fs::OpenOptionsExt;
+
+        const ERROR_SHARING_VIOLATION: i32 = 32;
+
+        match OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .share_mode(0)
+            .open(path)
+        {
+            Ok(file) => Ok(Some(Self { _file: file })),
+            Err(err) if err.raw_os_error() == Some(ERROR_SHARING_VIOLATION) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lock() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("build.lock");
+
+        let lock = FileLock::acquire(&path).unwrap();
+        assert!(FileLock::try_acquire(&path).unwrap().is_none());
+
+        drop(lock);
+        assert!(FileLock::try_acquire(&path).unwrap().is_some());
+    }
+}
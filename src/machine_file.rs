@@ -0,0 +1,380 @@
+//! Generation of meson machine files: see "Cross and Native File reference" in the Meson documentation.
+
+use crate::options;
+
+/// Meson binaries and the environment variables they are read from.
+const BINARIES: &[(&str, &str)] = &[
+    ("c", "CC"),
+    ("cpp", "CXX"),
+    ("ar", "AR"),
+    ("strip", "STRIP"),
+];
+
+/// Looks up `var` following the `cc` crate conventions: `<var>_<target>`,
+/// `<var>_<target_with_underscores>`, `<kind>_<var>` and finally `<var>`.
+fn tool_from_env(
+    var: &str,
+    target: Option<&str>,
+    kind: &str,
+    lookup: &impl Fn(&str) -> Option<String>,
+) -> Option<String> {
+    let mut names = Vec::new();
+    if let Some(target) = target {
+        names.push(format!("{var}_{target}"));
+        names.push(format!("{var}_{}", target.replace('-', "_")));
+    }
+    names.push(format!("{kind}_{var}"));
+    names.push(var.to_string());
+
+    names
+        .iter()
+        .filter_map(|name| lookup(name))
+        .find(|value| !value.trim().is_empty())
+}
+
+/// The `[binaries]` entries found by `lookup`.
+///
+/// `kind` is `HOST` or `TARGET`, as in the `cc` crate.
+pub(crate) fn binaries(
+    target: Option<&str>,
+    kind: &str,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> Vec<(&'static str, String)> {
+    BINARIES
+        .iter()
+        .filter_map(|(name, var)| {
+            let value = tool_from_env(var, target, kind, &lookup)?;
+            // Tools can be specified with a wrapper or arguments, like `ccache gcc`
+            let parts: Vec<&str> = value.split_whitespace().collect();
+            Some((*name, options::format_array(&parts)))
+        })
+        .collect()
+}
+
+/// Formats a machine file section.
+pub(crate) fn section(name: &str, entries: &[(&str, String)]) -> String {
+    let mut section = format!("[{name}]\n");
+    for (key, value) in entries {
+        section.push_str(&format!("{key} = {value}\n"));
+    }
+    section
+}
+
+/// The `[host_machine]` section of a cross file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct HostMachine {
+    pub system: String,
+    pub cpu_family: String,
+    pub cpu: String,
+    pub endian: &'static str,
+}
+
+impl HostMachine {
+    /// Maps a target triple like `aarch64-unknown-linux-gnu` to the meson machine description.
+    pub fn from_triple(triple: &str) -> Option<Self> {
+        let mut parts = triple.split('-');
+        let arch = parts.next()?;
+        let rest: Vec<&str> = parts.collect();
+
+        let (cpu_family, endian) = cpu_family(arch)?;
+        let system = system(&rest)?;
+
+        Some(Self {
+            system: system.to_string(),
+            cpu_family: cpu_family.to_string(),
+            cpu: arch.to_string(),
+            endian,
+        })
+    }
+
+    /// Describes the target from the `CARGO_CFG_TARGET_*` variables looked up by `lookup`.
+    ///
+    /// Returns `None` if they are not set or describe an unknown architecture. The `cpu` is
+    /// the architecture part of `triple`, e.g. `armv7`, which the variables do not provide.
+    ///
+    /// With a 32-bit `CARGO_CFG_TARGET_POINTER_WIDTH`, `powerpc64` and `sparc64` get their 32-bit
+    /// family. Like meson, the x32, n32 and ILP32 ABIs keep `x86_64`, `mips64` and `aarch64`.
+    pub fn from_cargo_cfg(triple: &str, lookup: impl Fn(&str) -> Option<String>) -> Option<Self> {
+        let arch = lookup("CARGO_CFG_TARGET_ARCH")?;
+        let os = lookup("CARGO_CFG_TARGET_OS")?;
+        let endian = match lookup("CARGO_CFG_TARGET_ENDIAN")?.as_str() {
+            "little" => "little",
+            "big" => "big",
+            _ => return None,
+        };
+
+        let cpu_family = match arch.as_str() {
+            "x86" => "x86",
+            "powerpc" => "ppc",
+            "powerpc64" => "ppc64",
+            "mips" | "mips32r6" => "mips",
+            "mips64" | "mips64r6" => "mips64",
+            "sparc" => "sparc",
+            arch @ ("x86_64" | "aarch64" | "arm" | "loongarch64" | "riscv32" | "riscv64"
+            | "s390x" | "sparc64" | "wasm32" | "wasm64") => arch,
+            _ => return None,
+        };
+        let cpu_family = match (
+            cpu_family,
+            lookup("CARGO_CFG_TARGET_POINTER_WIDTH").as_deref(),
+        ) {
+            ("ppc64", Some("32")) => "ppc",
+            ("sparc64", Some("32")) => "sparc",
+            (cpu_family, _) => cpu_family,
+        };
+
+        let system = match os.as_str() {
+            "macos" => "darwin".to_string(),
+            os => os.to_string(),
+        };
+
+        Some(Self {
+            system,
+            cpu_family: cpu_family.to_string(),
+            cpu: triple.split('-').next().unwrap_or(&arch).to_string(),
+            endian,
+        })
+    }
+
+    /// Formats the `[host_machine]` section.
+    pub fn section(&self) -> String {
+        let quote = |value: &str| format!("'{value}'");
+        section(
+            "host_machine",
+            &[
+                ("system", quote(&self.system)),
+                ("cpu_family", quote(&self.cpu_family)),
+                ("cpu", quote(&self.cpu)),
+                ("endian", quote(self.endian)),
+            ],
+        )
+    }
+}
+
+/// Meson CPU family and endianness of the triple architecture.
+fn cpu_family(arch: &str) -> Option<(&'static str, &'static str)> {
+    let family = match arch {
+        "x86_64" => ("x86_64", "little"),
+        "i386" | "i586" | "i686" => ("x86", "little"),
+        "aarch64" | "arm64" | "arm64e" => ("aarch64", "little"),
+        "aarch64_be" => ("aarch64", "big"),
+        "loongarch64" => ("loongarch64", "little"),
+        "mips" | "mipsisa32r6" => ("mips", "big"),
+        "mipsel" | "mipsisa32r6el" => ("mips", "little"),
+        "mips64" | "mipsisa64r6" => ("mips64", "big"),
+        "mips64el" | "mipsisa64r6el" => ("mips64", "little"),
+        "powerpc" => ("ppc", "big"),
+        "powerpc64" => ("ppc64", "big"),
+        "powerpc64le" => ("ppc64", "little"),
+        "s390x" => ("s390x", "big"),
+        "sparc64" | "sparcv9" => ("sparc64", "big"),
+        "wasm32" => ("wasm32", "little"),
+        "wasm64" => ("wasm64", "little"),
+        arch if arch.starts_with("riscv64") => ("riscv64", "little"),
+        arch if arch.starts_with("riscv32") => ("riscv32", "little"),
+        arch if arch.starts_with("armeb") => ("arm", "big"),
+        arch if arch.starts_with("arm") || arch.starts_with("thumb") => ("arm", "little"),
+        _ => return None,
+    };
+
+    Some(family)
+}
+
+/// Meson system name from the vendor, OS and environment parts of the triple.
+fn system(parts: &[&str]) -> Option<&'static str> {
+    // Checked in order, e.g. `linux-android` is android
+    const SYSTEMS: &[(&str, &str)] = &[
+        ("android", "android"),
+        ("androideabi", "android"),
+        ("linux", "linux"),
+        ("windows", "windows"),
+        ("darwin", "darwin"),
+        ("ios", "ios"),
+        ("freebsd", "freebsd"),
+        ("netbsd", "netbsd"),
+        ("openbsd", "openbsd"),
+        ("dragonfly", "dragonfly"),
+        ("emscripten", "emscripten"),
+        ("wasi", "wasi"),
+        ("none", "none"),
+    ];
+
+    SYSTEMS
+        .iter()
+        .find(|(name, _)| parts.contains(name))
+        .map(|(_, system)| *system)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_binaries() {
+        let env: HashMap<&str, &str> = [
+            ("CC", "gcc"),
+            ("CC_aarch64_unknown_linux_gnu", "aarch64-linux-gnu-gcc"),
+            ("CXX", "ccache g++"),
+            ("TARGET_AR", "llvm-ar"),
+            ("STRIP", " "),
+        ]
+        .into_iter()
+        .collect();
+        let lookup = |name: &str| env.get(name).map(|value| value.to_string());
+
+        assert_eq!(
+            binaries(None, "HOST", lookup),
+            [
+                ("c", "['gcc']".to_string()),
+                ("cpp", "['ccache','g++']".to_string()),
+            ]
+        );
+
+        assert_eq!(
+            binaries(Some("aarch64-unknown-linux-gnu"), "TARGET", lookup),
+            [
+                ("c", "['aarch64-linux-gnu-gcc']".to_string()),
+                ("cpp", "['ccache','g++']".to_string()),
+                ("ar", "['llvm-ar']".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_host_machine_from_triple() {
+        let machine = |system: &str, cpu_family: &str, cpu: &str, endian| HostMachine {
+            system: system.to_string(),
+            cpu_family: cpu_family.to_string(),
+            cpu: cpu.to_string(),
+            endian,
+        };
+
+        let cases = [
+            (
+                "x86_64-unknown-linux-gnu",
+                machine("linux", "x86_64", "x86_64", "little"),
+            ),
+            (
+                "aarch64-unknown-linux-gnu",
+                machine("linux", "aarch64", "aarch64", "little"),
+            ),
+            (
+                "armv7-unknown-linux-gnueabihf",
+                machine("linux", "arm", "armv7", "little"),
+            ),
+            (
+                "aarch64-linux-android",
+                machine("android", "aarch64", "aarch64", "little"),
+            ),
+            (
+                "x86_64-pc-windows-msvc",
+                machine("windows", "x86_64", "x86_64", "little"),
+            ),
+            (
+                "i686-pc-windows-gnu",
+                machine("windows", "x86", "i686", "little"),
+            ),
+            (
+                "aarch64-apple-darwin",
+                machine("darwin", "aarch64", "aarch64", "little"),
+            ),
+            (
+                "powerpc64-unknown-linux-gnu",
+                machine("linux", "ppc64", "powerpc64", "big"),
+            ),
+            (
+                "thumbv7em-none-eabihf",
+                machine("none", "arm", "thumbv7em", "little"),
+            ),
+        ];
+
+        for (triple, expected) in cases {
+            assert_eq!(HostMachine::from_triple(triple), Some(expected), "{triple}");
+        }
+
+        assert_eq!(HostMachine::from_triple("unknown-unknown-linux"), None);
+    }
+
+    #[test]
+    fn test_host_machine_from_cargo_cfg() {
+        let cfg: HashMap<&str, &str> = [
+            ("CARGO_CFG_TARGET_ARCH", "arm"),
+            ("CARGO_CFG_TARGET_OS", "linux"),
+            ("CARGO_CFG_TARGET_ENDIAN", "little"),
+            ("CARGO_CFG_TARGET_POINTER_WIDTH", "32"),
+        ]
+        .into_iter()
+        .collect();
+        let lookup = |name: &str| cfg.get(name).map(|value| value.to_string());
+
+        assert_eq!(
+            HostMachine::from_cargo_cfg("armv7-unknown-linux-gnueabihf", lookup),
+            Some(HostMachine {
+                system: "linux".to_string(),
+                cpu_family: "arm".to_string(),
+                cpu: "armv7".to_string(),
+                endian: "little",
+            })
+        );
+        assert_eq!(
+            HostMachine::from_cargo_cfg("armv7-unknown-linux-gnueabihf", |_| None),
+            None
+        );
+
+        let cfg = |arch: &str, pointer_width: &str| {
+            let cfg: HashMap<&str, String> = [
+                ("CARGO_CFG_TARGET_ARCH", arch.to_string()),
+                ("CARGO_CFG_TARGET_OS", "linux".to_string()),
+                ("CARGO_CFG_TARGET_ENDIAN", "little".to_string()),
+                ("CARGO_CFG_TARGET_POINTER_WIDTH", pointer_width.to_string()),
+            ]
+            .into_iter()
+            .collect();
+            move |name: &str| cfg.get(name).cloned()
+        };
+        let cpu_family = |triple, arch, pointer_width| {
+            HostMachine::from_cargo_cfg(triple, cfg(arch, pointer_width))
+                .unwrap()
+                .cpu_family
+        };
+        assert_eq!(
+            cpu_family("x86_64-unknown-linux-gnu", "x86_64", "64"),
+            "x86_64"
+        );
+        assert_eq!(
+            cpu_family("x86_64-unknown-linux-gnux32", "x86_64", "32"),
+            "x86_64"
+        );
+        assert_eq!(
+            cpu_family("mips64-unknown-linux-gnuabin32", "mips64", "32"),
+            "mips64"
+        );
+        assert_eq!(
+            cpu_family("aarch64-unknown-linux-gnu_ilp32", "aarch64", "32"),
+            "aarch64"
+        );
+    }
+
+    #[test]
+    fn test_host_machine_section() {
+        let machine = HostMachine::from_triple("aarch64-unknown-linux-gnu").unwrap();
+        assert_eq!(
+            machine.section(),
+            "[host_machine]\n\
+             system = 'linux'\n\
+             cpu_family = 'aarch64'\n\
+             cpu = 'aarch64'\n\
+             endian = 'little'\n"
+        );
+    }
+
+    #[test]
+    fn test_section() {
+        assert_eq!(
+            section("binaries", &[("c", "['gcc']".to_string())]),
+            "[binaries]\nc = ['gcc']\n"
+        );
+    }
+}
@@ -0,0 +1,200 @@
+//! Typed values of meson build options.
+
+use std::fmt;
+
+/// Value of a meson `feature` option.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Feature {
+    Enabled,
+    Disabled,
+    Auto,
+}
+
+impl Feature {
+    /// The value passed into `-D<option>=`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Feature::Enabled => "enabled",
+            Feature::Disabled => "disabled",
+            Feature::Auto => "auto",
+        }
+    }
+}
+
+impl fmt::Display for Feature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// How meson handles subproject wraps: see `--wrap-mode` in the Meson documentation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WrapMode {
+    Default,
+    /// Never download wraps, for offline builds.
+    NoDownload,
+    /// Always use the subprojects instead of system dependencies.
+    ForceFallback,
+    /// Never fall back to subprojects.
+    NoFallback,
+}
+
+impl WrapMode {
+    /// The value passed into `--wrap-mode`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WrapMode::Default => "default",
+            WrapMode::NoDownload => "nodownload",
+            WrapMode::ForceFallback => "forcefallback",
+            WrapMode::NoFallback => "nofallback",
+        }
+    }
+}
+
+impl fmt::Display for WrapMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Sanitizer instrumenting the compiled code: see the `b_sanitize` option in the Meson
+/// documentation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sanitizer {
+    None,
+    Address,
+    Thread,
+    Undefined,
+    Memory,
+    /// Address and undefined behavior sanitizers combined.
+    AddressUndefined,
+}
+
+impl Sanitizer {
+    /// The value passed into `-Db_sanitize=`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Sanitizer::None => "none",
+            Sanitizer::Address => "address",
+            Sanitizer::Thread => "thread",
+            Sanitizer::Undefined => "undefined",
+            Sanitizer::Memory => "memory",
+            Sanitizer::AddressUndefined => "address,undefined",
+        }
+    }
+}
+
+impl fmt::Display for Sanitizer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Link-time optimization mode: see the `b_lto_mode` option in the Meson documentation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LtoMode {
+    /// Full ("fat") LTO.
+    Default,
+    /// ThinLTO, supported by clang.
+    Thin,
+}
+
+impl LtoMode {
+    /// The value passed into `-Db_lto_mode=`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LtoMode::Default => "default",
+            LtoMode::Thin => "thin",
+        }
+    }
+}
+
+impl fmt::Display for LtoMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Kind of the libraries built by `library()`: see the `default_library` option in the Meson
+/// documentation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LibraryKind {
+    Static,
+    Shared,
+    /// Both static and shared libraries.
+    Both,
+}
+
+impl LibraryKind {
+    /// The value passed into `-Ddefault_library=`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LibraryKind::Static => "static",
+            LibraryKind::Shared => "shared",
+            LibraryKind::Both => "both",
+        }
+    }
+}
+
+impl fmt::Display for LibraryKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Unity build mode: see the `unity` option in the Meson documentation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnityMode {
+    On,
+    Off,
+    /// Unity builds only for subprojects.
+    Subprojects,
+}
+
+impl UnityMode {
+    /// The value passed into `-Dunity=`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UnityMode::On => "on",
+            UnityMode::Off => "off",
+            UnityMode::Subprojects => "subprojects",
+        }
+    }
+}
+
+impl fmt::Display for UnityMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Formats `values` as a meson array option value, e.g. `['-DFOO','-DBAR']`.
+///
+/// Meson parses arrays as Python literals, so each value is quoted as a Python string.
+pub(crate) fn format_array<S: AsRef<str>>(values: &[S]) -> String {
+    let values: Vec<String> = values
+        .iter()
+        .map(|value| {
+            let escaped = value.as_ref().replace('\\', "\\\\").replace('\'', "\\'");
+            format!("'{escaped}'")
+        })
+        .collect();
+
+    format!("[{}]", values.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_array() {
+        assert_eq!(format_array::<&str>(&[]), "[]");
+        assert_eq!(format_array(&["-DFOO"]), "['-DFOO']");
+        assert_eq!(format_array(&["-DFOO", "-DBAR"]), "['-DFOO','-DBAR']");
+        assert_eq!(format_array(&["a,b", "c"]), "['a,b','c']");
+        assert_eq!(format_array(&["it's"]), r"['it\'s']");
+        assert_eq!(format_array(&[r#"-DNAME="x""#]), r#"['-DNAME="x"']"#);
+        assert_eq!(format_array(&[r"C:\path\"]), r"['C:\\path\\']");
+    }
+}
@@ -0,0 +1,19 @@
+//! Summary of what a build did.
+
+use std::path::PathBuf;
+
+/// What [`crate::Config::build_with_outcome`] did.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuildOutcome {
+    /// The directory the project was compiled in.
+    pub build_dir: PathBuf,
+
+    /// The installation prefix.
+    pub install_dir: PathBuf,
+
+    /// Whether `meson setup` ran, i.e. the build directory was configured or reconfigured.
+    pub configured: bool,
+
+    /// Files installed by `meson install`, empty if installing is disabled.
+    pub installed_files: Vec<PathBuf>,
+}
@@ -0,0 +1,85 @@
+//! Results of `meson test` recorded in `meson-logs/testlog.json`.
+
+use std::time::Duration;
+
+use serde_json::Value;
+
+use crate::{introspect, Error, Result};
+
+/// The result of a single test run by `meson test`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TestResult {
+    /// Name of the test as in `meson.build`.
+    pub name: String,
+
+    /// Result reported by meson, e.g. `OK`, `FAIL`, `SKIP` or `TIMEOUT`.
+    pub result: String,
+
+    /// How long the test ran.
+    pub duration: Duration,
+
+    /// Output of the test, empty if not captured.
+    pub stdout: String,
+}
+
+impl TestResult {
+    /// Whether the test succeeded, including skipped and expected failures.
+    pub fn passed(&self) -> bool {
+        matches!(self.result.as_str(), "OK" | "SKIP" | "EXPECTEDFAIL")
+    }
+}
+
+/// Parses `testlog.json`, which contains one JSON object per test and line.
+pub(crate) fn parse_test_log(log: &str) -> Result<Vec<TestResult>> {
+    log.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let entry: Value = serde_json::from_str(line)?;
+            let duration = introspect::field(&entry, "duration")?
+                .as_f64()
+                .ok_or_else(|| Error::IntrospectField("duration".to_string()))?;
+
+            Ok(TestResult {
+                name: introspect::string_field(&entry, "name")?,
+                result: introspect::string_field(&entry, "result")?,
+                duration: Duration::from_secs_f64(duration.max(0.0)),
+                stdout: introspect::optional_string_field(&entry, "stdout")?.unwrap_or_default(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `testlog.json` captured from a project with a passing and a failing test.
+    const TEST_LOG: &str = r#"{"name": "answer", "stdout": "42\n", "result": "OK", "starttime": 1700000000.5, "duration": 0.25, "returncode": 0, "env": {}, "command": ["/src/build/answer_test"]}
+{"name": "broken", "stdout": "", "result": "FAIL", "starttime": 1700000000.75, "duration": 1.5, "returncode": 1, "env": {}, "command": ["/src/build/broken_test"]}
+"#;
+
+    #[test]
+    fn test_parse_test_log() {
+        let results = parse_test_log(TEST_LOG).unwrap();
+
+        assert_eq!(
+            results,
+            [
+                TestResult {
+                    name: "answer".to_string(),
+                    result: "OK".to_string(),
+                    duration: Duration::from_millis(250),
+                    stdout: "42\n".to_string(),
+                },
+                TestResult {
+                    name: "broken".to_string(),
+                    result: "FAIL".to_string(),
+                    duration: Duration::from_millis(1500),
+                    stdout: String::new(),
+                },
+            ]
+        );
+        assert!(results[0].passed());
+        assert!(!results[1].passed());
+    }
+}
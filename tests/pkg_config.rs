@@ -0,0 +1,37 @@
+use std::path::Path;
+
+#[test]
+fn test_emit_pkg_config() {
+    let out = tempfile::tempdir().unwrap();
+    let source_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/projects/pkgconfig");
+
+    // Needs a real meson and ninja installation
+    let Ok(config) = meson_ext_rs::find_meson() else {
+        eprintln!("meson not found, skipping");
+        return;
+    };
+    let config = config
+        .with_profile("release")
+        .with_option("libdir", "lib")
+        .with_out_path(out.path());
+
+    config.clone().build(&source_dir).unwrap();
+
+    let missing = config.emit_pkg_config("missing");
+    assert!(matches!(
+        missing,
+        Err(meson_ext_rs::Error::PkgConfigFileNotFound(_))
+    ));
+
+    let library = config.emit_pkg_config("answer").unwrap();
+    let install_dir = config.install_dir().unwrap();
+    assert!(library
+        .include_paths
+        .iter()
+        .any(|path| path.starts_with(&install_dir)));
+    assert!(library
+        .link_paths
+        .iter()
+        .any(|path| path.starts_with(&install_dir)));
+    assert_eq!(library.libs, ["answer"]);
+}
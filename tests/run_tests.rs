@@ -0,0 +1,26 @@
+use std::path::Path;
+
+use meson_ext_rs::Error;
+
+#[test]
+fn test_run_tests() {
+    let out = tempfile::tempdir().unwrap();
+    let source_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/projects/tests");
+
+    // Needs a real meson and ninja installation
+    let Ok(config) = meson_ext_rs::find_meson() else {
+        eprintln!("meson not found, skipping");
+        return;
+    };
+    let config = config.with_profile("debug").with_out_path(out.path());
+
+    config.clone().build(&source_dir).unwrap();
+
+    config.run_tests_filtered(None, &["passing"]).unwrap();
+
+    let err = config.run_tests().unwrap_err();
+    assert!(matches!(err, Error::MesonTestsFailed(_)));
+
+    let err = config.run_tests_filtered(None, &["failing"]).unwrap_err();
+    assert!(matches!(err, Error::MesonTestsFailed(_)));
+}